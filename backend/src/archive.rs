@@ -0,0 +1,171 @@
+//! Just enough of the tar format to stream an export without buffering it.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use axum::body::Bytes;
+use chrono::{DateTime, Utc};
+use futures_util::{stream, Stream, StreamExt};
+
+use crate::{
+    s3,
+    storage::{GetOptions, ObjectBody, Storage},
+    types::asset::MetadataWithName,
+};
+
+const BLOCK_SIZE: usize = 512;
+const NAME_LEN: usize = 100;
+
+/// Zero-padded octal digits followed by a NUL.
+fn write_octal(field: &mut [u8], value: u64) {
+    let len = field.len() - 1;
+    let digits = format!("{:0len$o}", value, len = len);
+    field[..len].copy_from_slice(digits.as_bytes());
+    field[len] = 0;
+}
+
+/// Octal if it fits, base-256 otherwise, which GNU tar and libarchive read for sizes of 8 GiB or
+/// more.
+fn write_number(field: &mut [u8], value: u64) {
+    if value < 1 << (3 * (field.len() - 1)) {
+        write_octal(field, value);
+    } else {
+        field.fill(0);
+        field[0] = 0x80;
+        let len = field.len();
+        field[len - 8..].copy_from_slice(&value.to_be_bytes());
+    }
+}
+
+fn header(path: &str, size: u64, mtime: DateTime<Utc>, typeflag: u8) -> [u8; BLOCK_SIZE] {
+    let mut header = [0; BLOCK_SIZE];
+    let path = path.as_bytes();
+    let path = &path[..path.len().min(NAME_LEN)];
+    header[..path.len()].copy_from_slice(path);
+    write_octal(&mut header[100..108], 0o644);
+    write_octal(&mut header[108..116], 0);
+    write_octal(&mut header[116..124], 0);
+    write_number(&mut header[124..136], size);
+    write_octal(&mut header[136..148], mtime.timestamp().max(0) as u64);
+    header[156] = typeflag;
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+
+    // The checksum is taken with its own field filled with spaces, and ends with a NUL and a
+    // space.
+    header[148..156].fill(b' ');
+    let checksum = header.iter().map(|byte| u64::from(*byte)).sum();
+    write_octal(&mut header[148..155], checksum);
+
+    header
+}
+
+fn padding(size: u64) -> Bytes {
+    let len = (BLOCK_SIZE - (size % BLOCK_SIZE as u64) as usize) % BLOCK_SIZE;
+    Bytes::from(vec![0; len])
+}
+
+/// Header of a regular file, preceded by a PAX extended header if the path does not fit in the
+/// header itself.
+fn file_header(path: &str, size: u64, mtime: DateTime<Utc>) -> Bytes {
+    let mut bytes = Vec::with_capacity(BLOCK_SIZE);
+    if path.len() > NAME_LEN {
+        // The length of a record counts its own digits.
+        let base = " path=\n".len() + path.len();
+        let mut len = base + 1;
+        while len != base + len.to_string().len() {
+            len = base + len.to_string().len();
+        }
+        let record = format!("{} path={}\n", len, path);
+        bytes.extend_from_slice(&header("PaxHeader", record.len() as u64, mtime, b'x'));
+        bytes.extend_from_slice(record.as_bytes());
+        bytes.extend_from_slice(&padding(record.len() as u64));
+    }
+    bytes.extend_from_slice(&header(path, size, mtime, b'0'));
+    Bytes::from(bytes)
+}
+
+/// Chunks of zeros to fill up a file whose body failed midway, as its size is already in the
+/// header.
+static ZEROS: [u8; 64 * 1024] = [0; 64 * 1024];
+
+/// Reads the body of a photo into exactly `size` bytes. The header is sent before the body is read,
+/// so a body which fails or ends early is filled up with zeros to keep the rest of the tar intact.
+fn exact_body(name: String, body: ObjectBody, size: u64) -> impl Stream<Item = Bytes> + Send {
+    stream::unfold((Some(body), 0), move |(body, sent)| {
+        let name = name.clone();
+        async move {
+            if sent >= size {
+                return None;
+            }
+            if let Some(mut body) = body {
+                match body.next().await {
+                    Some(Ok(chunk)) => {
+                        let chunk = chunk.slice(..chunk.len().min((size - sent) as usize));
+                        let sent = sent + chunk.len() as u64;
+                        return Some((chunk, (Some(body), sent)));
+                    }
+                    Some(Err(error)) => {
+                        tracing::warn!(%name, %error, "failed to read photo to export");
+                    }
+                    None => tracing::warn!(%name, "photo to export is shorter than expected"),
+                }
+            }
+            let len = ZEROS.len().min((size - sent) as usize);
+            Some((Bytes::from_static(&ZEROS[..len]), (None, sent + len as u64)))
+        }
+    })
+}
+
+async fn export_photo(
+    storage: &dyn Storage,
+    MetadataWithName { metadata, name }: MetadataWithName,
+) -> Result<impl Stream<Item = Bytes> + Send> {
+    let json = serde_json::to_vec_pretty(&metadata)?;
+    let json_len = json.len() as u64;
+    let object = s3::get_photo(storage, &name, GetOptions::default()).await?;
+    let size = u64::try_from(object.content_length)?;
+    let mtime = object.last_modified.unwrap_or(metadata.created_at);
+
+    let head = [
+        file_header(
+            &format!("metadata/{}.json", name),
+            json_len,
+            metadata.created_at,
+        ),
+        Bytes::from(json),
+        padding(json_len),
+        file_header(&format!("photo/{}", name), size, mtime),
+    ];
+    Ok(stream::iter(head)
+        .chain(exact_body(name, object.body, size))
+        .chain(stream::once(async move { padding(size) })))
+}
+
+/// Streams a tar with each photo under `photo/` and its metadata under `metadata/`, the layout
+/// `cheph-admin import` reads. Photos are fetched one by one as the stream is polled. Photos which
+/// cannot be fetched are left out, so that the tar always ends properly.
+pub fn export(
+    storage: Arc<dyn Storage>,
+    metadatas: Vec<MetadataWithName>,
+) -> impl Stream<Item = Result<Bytes>> + Send {
+    stream::iter(metadatas)
+        .then(move |metadata| {
+            let storage = storage.clone();
+            async move {
+                let name = metadata.name.clone();
+                match export_photo(&*storage, metadata).await {
+                    Ok(entry) => entry.left_stream(),
+                    Err(error) => {
+                        tracing::warn!(%name, %error, "skipping photo which cannot be exported");
+                        stream::empty().right_stream()
+                    }
+                }
+            }
+        })
+        .flatten()
+        .chain(stream::once(async {
+            Bytes::from_static(&[0; 2 * BLOCK_SIZE])
+        }))
+        .map(Ok)
+}
@@ -0,0 +1,623 @@
+//! Maintenance tasks on the storage of a cheph deployment. Reads the same environment variables as
+//! the server.
+
+use std::{
+    collections::{BTreeSet, HashSet},
+    future::Future,
+    path::{Component, Path, PathBuf},
+    process::ExitCode,
+    sync::Mutex,
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use axum::body::Body;
+use chrono::{DateTime, Utc};
+use futures_util::{stream, StreamExt, TryStreamExt};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::{fs, io::AsyncWriteExt};
+use tokio_util::io::ReaderStream;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+use cheph_backend::{
+    photo, s3,
+    storage::{self, ObjectInfo, Storage, StorageError},
+    types::asset::{is_valid_name, Metadata},
+};
+
+const USAGE: &str = "\
+usage: cheph-admin <command> [options]
+
+commands:
+    list                  print every metadata as a JSON line
+    verify                report orphan photos, orphan metadatas, broken metadatas and orphan
+                          resized photos, and exit with 1 if there is any
+    gc                    delete what verify reports, except broken metadatas, and direct
+                          uploads which were never committed
+        --dry-run                   only print what would be deleted
+        --grace-period-secs <secs>  keep what is younger than this, which may be an upload in
+                                    progress (default: 86400)
+    reindex               rebuild the index snapshot
+        --reprocess                 regenerate resized photos and EXIF of every photo first
+    export <directory>    copy every photo and metadata into a directory
+    import <directory>    upload every photo and metadata from a directory made by export
+        --overwrite                 replace photos which already exist
+    bulk-import <directory>
+                          upload every file under a directory, tagged with the names of its
+                          subdirectories, and print a JSON line per file. Files whose name or
+                          content already exists are skipped, so running it again resumes an
+                          interrupted import
+        --creator <email>           email to record as the creator (required)
+        --concurrency <count>       files to upload at once (default: 4)";
+
+enum Command {
+    List,
+    Verify,
+    Gc {
+        dry_run: bool,
+        grace_period: Duration,
+    },
+    Reindex {
+        reprocess: bool,
+    },
+    Export {
+        directory: PathBuf,
+    },
+    Import {
+        directory: PathBuf,
+        overwrite: bool,
+    },
+    BulkImport {
+        directory: PathBuf,
+        creator_email: String,
+        concurrency: usize,
+    },
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Command> {
+    let command = args.next().context("missing command")?;
+
+    let mut dry_run = false;
+    let mut grace_period = Duration::from_secs(24 * 60 * 60);
+    let mut reprocess = false;
+    let mut overwrite = false;
+    let mut creator_email = None;
+    let mut concurrency = 4;
+    let mut positionals = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dry-run" => dry_run = true,
+            "--grace-period-secs" => {
+                let secs = args
+                    .next()
+                    .context("missing value of --grace-period-secs")?;
+                grace_period = Duration::from_secs(secs.parse()?);
+            }
+            "--reprocess" => reprocess = true,
+            "--overwrite" => overwrite = true,
+            "--creator" => creator_email = Some(args.next().context("missing value of --creator")?),
+            "--concurrency" => {
+                let count = args.next().context("missing value of --concurrency")?;
+                concurrency = count.parse()?;
+                if concurrency == 0 {
+                    bail!("--concurrency must be positive");
+                }
+            }
+            _ if arg.starts_with("--") => bail!("unknown option `{}`", arg),
+            _ => positionals.push(arg),
+        }
+    }
+
+    let directory = || match positionals.as_slice() {
+        [directory] => Ok(PathBuf::from(directory)),
+        _ => Err(anyhow::anyhow!("`{}` takes a directory", command)),
+    };
+    Ok(match command.as_str() {
+        "list" => Command::List,
+        "verify" => Command::Verify,
+        "gc" => Command::Gc {
+            dry_run,
+            grace_period,
+        },
+        "reindex" => Command::Reindex { reprocess },
+        "export" => Command::Export {
+            directory: directory()?,
+        },
+        "import" => Command::Import {
+            directory: directory()?,
+            overwrite,
+        },
+        "bulk-import" => Command::BulkImport {
+            directory: directory()?,
+            creator_email: creator_email.context("`bulk-import` takes --creator")?,
+            concurrency,
+        },
+        _ => bail!("unknown command `{}`", command),
+    })
+}
+
+async fn list(storage: &dyn Storage) -> Result<ExitCode> {
+    for metadata in s3::list_metadatas(storage).await? {
+        println!("{}", serde_json::to_string(&metadata)?);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+async fn verify(storage: &dyn Storage) -> Result<ExitCode> {
+    let report = s3::check_consistency(storage).await?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(if report.is_consistent() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+/// Whether an object was last modified longer than `grace_period` ago.
+fn is_older_than(last_modified: Option<DateTime<Utc>>, grace_period: Duration) -> bool {
+    match last_modified {
+        Some(last_modified) => (Utc::now() - last_modified)
+            .to_std()
+            .map(|age| age >= grace_period)
+            .unwrap_or(false),
+        None => true,
+    }
+}
+
+/// Object information, or `None` if the object is missing, to check again what the report found.
+async fn head_if_exists(
+    head: impl Future<Output = Result<ObjectInfo>>,
+) -> Result<Option<ObjectInfo>> {
+    match head.await {
+        Ok(object) => Ok(Some(object)),
+        Err(error) => match error.downcast_ref::<StorageError>() {
+            Some(StorageError::NotFound) => Ok(None),
+            _ => Err(error),
+        },
+    }
+}
+
+/// Deletes what the consistency report found. Uploads and deletions may be in progress while the
+/// report is made, so everything younger than `grace_period` is kept, and the counterpart of each
+/// orphan is looked up again right before deleting it.
+async fn gc(storage: &dyn Storage, dry_run: bool, grace_period: Duration) -> Result<ExitCode> {
+    let report = s3::check_consistency(storage).await?;
+
+    for name in &report.orphan_photos {
+        let object = match head_if_exists(s3::head_photo(storage, name)).await? {
+            Some(object) => object,
+            None => continue,
+        };
+        if !is_older_than(object.last_modified, grace_period) {
+            println!("keep photo {}, which may be still uploading", name);
+            continue;
+        }
+        if head_if_exists(s3::head_metadata(storage, name))
+            .await?
+            .is_some()
+        {
+            println!("keep photo {}, whose metadata has been written since", name);
+            continue;
+        }
+        println!("delete photo {}", name);
+        if !dry_run {
+            s3::delete_photo_objects(storage, name).await?;
+        }
+    }
+    for name in &report.orphan_metadatas {
+        let object = match head_if_exists(s3::head_metadata(storage, name)).await? {
+            Some(object) => object,
+            None => continue,
+        };
+        if !is_older_than(object.last_modified, grace_period) {
+            println!("keep metadata {}, which may be still uploading", name);
+            continue;
+        }
+        if head_if_exists(s3::head_photo(storage, name))
+            .await?
+            .is_some()
+        {
+            println!("keep metadata {}, whose photo has been written since", name);
+            continue;
+        }
+        println!("delete metadata {}", name);
+        if !dry_run {
+            s3::delete_metadata(storage, name).await?;
+        }
+    }
+    for key in &report.orphan_thumbnails {
+        let object = match head_if_exists(storage.head_object(key)).await? {
+            Some(object) => object,
+            None => continue,
+        };
+        if !is_older_than(object.last_modified, grace_period) {
+            println!("keep {}, which may be still uploading", key);
+            continue;
+        }
+        if let Some(name) = s3::name_from_key_thumbnail(key) {
+            if head_if_exists(s3::head_photo(storage, name))
+                .await?
+                .is_some()
+            {
+                println!("keep {}, whose photo has been written since", key);
+                continue;
+            }
+        }
+        println!("delete {}", key);
+        if !dry_run {
+            storage.delete_object(key).await?;
+        }
+    }
+    for id in &report.uncommitted_uploads {
+        let object = match head_if_exists(s3::head_upload(storage, id)).await? {
+            Some(object) => object,
+            None => continue,
+        };
+        if !is_older_than(object.last_modified, grace_period) {
+            println!("keep upload {}, which may be still uploading", id);
+            continue;
+        }
+        println!("delete upload {}", id);
+        if !dry_run {
+            s3::delete_upload(storage, id).await?;
+        }
+    }
+    for name in &report.broken_metadatas {
+        println!(
+            "keep broken metadata {}, which needs to be fixed by hand",
+            name
+        );
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+async fn reindex(storage: &dyn Storage, reprocess: bool) -> Result<ExitCode> {
+    let mut code = ExitCode::SUCCESS;
+    if reprocess {
+        let names = s3::list_metadata_objects(storage).await?;
+        s3::defer_snapshot_updates(async {
+            for (name, _) in names {
+                match s3::reprocess_photo(storage, &name).await {
+                    Ok(_) => println!("reprocessed {}", name),
+                    Err(error) => {
+                        eprintln!("failed to reprocess {}: {:#}", name, error);
+                        code = ExitCode::FAILURE;
+                    }
+                }
+            }
+        })
+        .await;
+    }
+
+    let count = s3::rebuild_snapshot(storage).await?;
+    println!("indexed {} metadatas", count);
+
+    Ok(code)
+}
+
+async fn export(storage: &dyn Storage, directory: &Path) -> Result<ExitCode> {
+    for prefix in ["photo/", "metadata/"] {
+        for object in storage.list_objects(prefix).await? {
+            let path = directory.join(&object.key);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+            let mut file = fs::File::create(&path).await?;
+            let mut body = storage.get_object(&object.key).await?.body;
+            while let Some(chunk) = body.try_next().await? {
+                file.write_all(&chunk).await?;
+            }
+            file.sync_all().await?;
+            println!("exported {}", object.key);
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+async fn import_photo(
+    storage: &dyn Storage,
+    directory: &Path,
+    name: &str,
+    overwrite: bool,
+) -> Result<()> {
+    let metadata_path = directory.join("metadata").join(format!("{}.json", name));
+    let metadata = serde_json::from_slice::<Metadata>(&fs::read(metadata_path).await?)?;
+
+    let file = fs::File::open(directory.join("photo").join(name)).await?;
+    let (content_type, body) =
+        photo::sniff_body(Body::wrap_stream(ReaderStream::new(file))).await?;
+    let content_type = content_type.context("unsupported content type")?;
+
+    match s3::upload_photo(storage, name, metadata, body, content_type, overwrite).await {
+        Ok(_) => Ok(()),
+        Err(error) => match error.downcast_ref::<StorageError>() {
+            Some(StorageError::PreconditionFailed) => {
+                bail!("photo already exists, pass --overwrite to replace it")
+            }
+            _ => Err(error),
+        },
+    }
+}
+
+async fn import(storage: &dyn Storage, directory: &Path, overwrite: bool) -> Result<ExitCode> {
+    let mut code = ExitCode::SUCCESS;
+    let mut entries = fs::read_dir(directory.join("metadata")).await?;
+    s3::defer_snapshot_updates(async {
+        while let Some(entry) = entries.next_entry().await? {
+            let file_name = entry.file_name();
+            let name = match file_name
+                .to_str()
+                .and_then(|file_name| file_name.strip_suffix(".json"))
+            {
+                Some(name) => name,
+                None => continue,
+            };
+            match import_photo(storage, directory, name, overwrite).await {
+                Ok(()) => println!("imported {}", name),
+                Err(error) => {
+                    eprintln!("failed to import {}: {:#}", name, error);
+                    code = ExitCode::FAILURE;
+                }
+            }
+        }
+        anyhow::Ok(())
+    })
+    .await?;
+    s3::list_metadatas(storage).await?;
+    Ok(code)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum BulkImportStatus {
+    Imported,
+    /// A photo with the same name exists.
+    Exists,
+    /// A photo with the same content exists.
+    Duplicate,
+    Unsupported,
+    InvalidName,
+    Failed,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BulkImportEntry<'a> {
+    path: &'a Path,
+    name: String,
+    status: BulkImportStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+struct BulkImport<'a> {
+    storage: &'a dyn Storage,
+    directory: &'a Path,
+    creator_email: &'a str,
+    /// Names and hashes which exist or are being imported, so that concurrent files do not race
+    /// each other.
+    names: Mutex<HashSet<String>>,
+    hashes: Mutex<HashSet<String>>,
+}
+
+async fn walk(directory: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut directories = vec![directory.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        let mut entries = fs::read_dir(&directory).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let file_type = entry.file_type().await?;
+            if file_type.is_dir() {
+                directories.push(entry.path());
+            } else if file_type.is_file() {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+async fn hash_file(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    let mut stream = ReaderStream::new(fs::File::open(path).await?);
+    while let Some(chunk) = stream.try_next().await? {
+        hasher.update(&chunk);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+impl BulkImport<'_> {
+    async fn import_file<'a>(&self, path: &'a Path) -> BulkImportEntry<'a> {
+        let relative = path.strip_prefix(self.directory).unwrap_or(path);
+        let name = relative
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let tags = relative
+            .parent()
+            .into_iter()
+            .flat_map(Path::components)
+            .filter_map(|component| match component {
+                Component::Normal(tag) => Some(tag.to_string_lossy().trim().to_string()),
+                _ => None,
+            })
+            .filter(|tag| !tag.is_empty())
+            .collect();
+
+        let (status, error) = match self.try_import_file(path, &name, tags).await {
+            Ok(status) => (status, None),
+            Err(error) => (BulkImportStatus::Failed, Some(format!("{:#}", error))),
+        };
+        BulkImportEntry {
+            path,
+            name,
+            status,
+            error,
+        }
+    }
+
+    async fn try_import_file(
+        &self,
+        path: &Path,
+        name: &str,
+        tags: BTreeSet<String>,
+    ) -> Result<BulkImportStatus> {
+        if !is_valid_name(name) {
+            return Ok(BulkImportStatus::InvalidName);
+        }
+        if !self.names.lock().unwrap().insert(name.to_string()) {
+            return Ok(BulkImportStatus::Exists);
+        }
+
+        let result = self.upload_file(path, name, tags).await;
+        if !matches!(result, Ok(BulkImportStatus::Imported)) {
+            self.names.lock().unwrap().remove(name);
+        }
+        result
+    }
+
+    async fn upload_file(
+        &self,
+        path: &Path,
+        name: &str,
+        tags: BTreeSet<String>,
+    ) -> Result<BulkImportStatus> {
+        let sha256 = hash_file(path).await?;
+        if !self.hashes.lock().unwrap().insert(sha256.clone()) {
+            return Ok(BulkImportStatus::Duplicate);
+        }
+
+        let result = async {
+            let file = fs::File::open(path).await?;
+            let (content_type, body) =
+                photo::sniff_body(Body::wrap_stream(ReaderStream::new(file))).await?;
+            let content_type = match content_type {
+                Some(content_type) => content_type,
+                None => return Ok(BulkImportStatus::Unsupported),
+            };
+
+            let metadata = Metadata {
+                creator_email: self.creator_email.to_string(),
+                created_at: Utc::now(),
+                tags,
+                description: String::new(),
+                exif: None,
+                content_type: None,
+                size: None,
+                sha256: None,
+            };
+            match s3::upload_photo(self.storage, name, metadata, body, content_type, false).await {
+                Ok(_) => Ok(BulkImportStatus::Imported),
+                Err(error) => match error.downcast_ref::<StorageError>() {
+                    Some(StorageError::PreconditionFailed) => Ok(BulkImportStatus::Exists),
+                    _ => Err(error),
+                },
+            }
+        }
+        .await;
+        if !matches!(result, Ok(BulkImportStatus::Imported)) {
+            self.hashes.lock().unwrap().remove(&sha256);
+        }
+        result
+    }
+}
+
+async fn bulk_import(
+    storage: &dyn Storage,
+    directory: &Path,
+    creator_email: &str,
+    concurrency: usize,
+) -> Result<ExitCode> {
+    let existing = s3::list_metadatas(storage).await?;
+    let import = BulkImport {
+        storage,
+        directory,
+        creator_email,
+        names: Mutex::new(
+            existing
+                .iter()
+                .map(|metadata| metadata.name.clone())
+                .collect(),
+        ),
+        hashes: Mutex::new(
+            existing
+                .iter()
+                .filter_map(|metadata| metadata.metadata.sha256.clone())
+                .collect(),
+        ),
+    };
+
+    let files = walk(directory).await?;
+    let mut code = ExitCode::SUCCESS;
+    s3::defer_snapshot_updates(async {
+        let mut entries = stream::iter(&files)
+            .map(|path| import.import_file(path))
+            .buffer_unordered(concurrency);
+        while let Some(entry) = entries.next().await {
+            if matches!(entry.status, BulkImportStatus::Failed) {
+                code = ExitCode::FAILURE;
+            }
+            println!("{}", serde_json::to_string(&entry)?);
+        }
+        anyhow::Ok(())
+    })
+    .await?;
+    s3::list_metadatas(storage).await?;
+    Ok(code)
+}
+
+async fn run(command: Command) -> Result<ExitCode> {
+    let storage = storage::from_config().await?;
+    let storage = &*storage;
+    match command {
+        Command::List => list(storage).await,
+        Command::Verify => verify(storage).await,
+        Command::Gc {
+            dry_run,
+            grace_period,
+        } => gc(storage, dry_run, grace_period).await,
+        Command::Reindex { reprocess } => reindex(storage, reprocess).await,
+        Command::Export { directory } => export(storage, &directory).await,
+        Command::Import {
+            directory,
+            overwrite,
+        } => import(storage, &directory, overwrite).await,
+        Command::BulkImport {
+            directory,
+            creator_email,
+            concurrency,
+        } => bulk_import(storage, &directory, &creator_email, concurrency).await,
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::EnvFilter::new(
+            std::env::var("RUST_LOG").unwrap_or_else(|_| "warn".into()),
+        ))
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
+        .init();
+
+    let command = match parse_args(std::env::args().skip(1)) {
+        Ok(command) => command,
+        Err(error) => {
+            eprintln!("error: {}\n\n{}", error, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(command).await {
+        Ok(code) => code,
+        Err(error) => {
+            eprintln!("error: {:#}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -3,7 +3,7 @@ use std::path::PathBuf;
 use anyhow::Result;
 use jsonwebtoken::{DecodingKey, EncodingKey};
 use once_cell::sync::Lazy;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use url::Url;
 
 pub static CONFIG: Lazy<Config> =
@@ -17,6 +17,50 @@ fn default_static_file_directory() -> PathBuf {
     PathBuf::from("../frontend/build")
 }
 
+fn default_local_storage_directory() -> PathBuf {
+    PathBuf::from("./data")
+}
+
+fn default_metadata_index_refresh_interval_secs() -> u64 {
+    60
+}
+
+fn default_thumbnail_sizes() -> Vec<u32> {
+    vec![512, 1024]
+}
+
+fn default_thumbnail_max_source_size() -> i64 {
+    64 * 1024 * 1024
+}
+
+fn default_cache_control() -> String {
+    "private, no-cache".to_string()
+}
+
+fn default_presigned_url_expiry_secs() -> u64 {
+    300
+}
+
+fn default_max_upload_size() -> i64 {
+    1024 * 1024 * 1024
+}
+
+fn default_trash_retention_secs() -> u64 {
+    30 * 24 * 60 * 60
+}
+
+fn default_multipart_threshold() -> usize {
+    16 * 1024 * 1024
+}
+
+fn default_multipart_part_size() -> usize {
+    8 * 1024 * 1024
+}
+
+fn default_multipart_concurrency() -> usize {
+    4
+}
+
 fn deserialize_allowed_emails<'de, D>(d: D) -> Result<Vec<String>, D::Error>
 where
     D: serde::Deserializer<'de>,
@@ -36,6 +80,104 @@ where
     ))
 }
 
+fn deserialize_thumbnail_sizes<'de, D>(d: D) -> Result<Vec<u32>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let s = std::borrow::Cow::<'_, str>::deserialize(d)?;
+    let mut sizes = s
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::parse)
+        .collect::<Result<Vec<u32>, _>>()
+        .map_err(serde::de::Error::custom)?;
+    sizes.sort_unstable();
+    sizes.dedup();
+    Ok(sizes)
+}
+
+/// Read from `OIDC_{ID}_*` variables for every id listed in `OIDC_PROVIDERS`.
+#[derive(Deserialize)]
+pub struct OidcProviderConfig {
+    #[serde(skip)]
+    pub id: String,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    pub issuer_url: Url,
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+fn deserialize_oidc_providers<'de, D>(d: D) -> Result<Vec<OidcProviderConfig>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let s = std::borrow::Cow::<'_, str>::deserialize(d)?;
+    s.split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| {
+            let prefix = format!("OIDC_{}_", id.to_uppercase().replace('-', "_"));
+            let mut config = envy::prefixed(prefix)
+                .from_env::<OidcProviderConfig>()
+                .map_err(serde::de::Error::custom)?;
+            config.id = id.to_string();
+            Ok(config)
+        })
+        .collect()
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Can only read.
+    #[default]
+    Viewer,
+    /// Can upload, and edit or delete their own photos.
+    Uploader,
+    /// Can do everything.
+    Admin,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ThumbnailFormat {
+    #[default]
+    Jpeg,
+    /// Lossless WebP.
+    Webp,
+}
+
+impl ThumbnailFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ThumbnailFormat::Jpeg => "image/jpeg",
+            ThumbnailFormat::Webp => "image/webp",
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    #[default]
+    S3,
+    Local,
+    Memory,
+}
+
+/// How photo bytes reach the browser.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PhotoDelivery {
+    /// Streamed through this server.
+    #[default]
+    Proxy,
+    /// Redirected to a presigned URL, if the storage backend supports it.
+    Redirect,
+}
+
 #[derive(Deserialize)]
 pub struct Config {
     #[serde(default = "default_listen_addr")]
@@ -44,22 +186,122 @@ pub struct Config {
     #[serde(default = "default_static_file_directory")]
     pub static_file_directory: PathBuf,
 
+    /// Allow-list from before roles existed. Its members are admins.
     #[serde(default, deserialize_with = "deserialize_allowed_emails")]
     pub allowed_emails: Vec<String>,
 
-    pub github_client_id: String,
-    pub github_client_secret: String,
+    #[serde(default, deserialize_with = "deserialize_allowed_emails")]
+    pub viewer_emails: Vec<String>,
+
+    #[serde(default, deserialize_with = "deserialize_allowed_emails")]
+    pub uploader_emails: Vec<String>,
+
+    #[serde(default, deserialize_with = "deserialize_allowed_emails")]
+    pub admin_emails: Vec<String>,
+
+    #[serde(default)]
+    pub github_client_id: Option<String>,
+    #[serde(default)]
+    pub github_client_secret: Option<String>,
+
+    #[serde(default, deserialize_with = "deserialize_oidc_providers")]
+    pub oidc_providers: Vec<OidcProviderConfig>,
 
     pub public_url: Url,
 
     #[serde(deserialize_with = "deserialize_jwt_secret")]
     pub jwt_secret: (EncodingKey, DecodingKey),
 
-    pub s3_bucket_name: String,
+    #[serde(default)]
+    pub storage_backend: StorageBackend,
+
+    #[serde(default)]
+    pub s3_bucket_name: Option<String>,
+
+    /// Uploads to S3 bigger than this in bytes are split into parts.
+    #[serde(default = "default_multipart_threshold")]
+    pub multipart_threshold: usize,
+
+    /// At least 5 MiB, as S3 requires.
+    #[serde(default = "default_multipart_part_size")]
+    pub multipart_part_size: usize,
+
+    /// Parts uploaded at the same time. Each of them is buffered in memory.
+    #[serde(default = "default_multipart_concurrency")]
+    pub multipart_concurrency: usize,
+
+    #[serde(default = "default_local_storage_directory")]
+    pub local_storage_directory: PathBuf,
+
+    /// Logs orphan photos and metadatas on startup.
+    #[serde(default)]
+    pub check_consistency_on_startup: bool,
+
+    #[serde(default)]
+    pub metadata_index: bool,
+
+    #[serde(default = "default_metadata_index_refresh_interval_secs")]
+    pub metadata_index_refresh_interval_secs: u64,
+
+    /// Longest edges of resized variants generated on upload.
+    #[serde(
+        default = "default_thumbnail_sizes",
+        deserialize_with = "deserialize_thumbnail_sizes"
+    )]
+    pub thumbnail_sizes: Vec<u32>,
+
+    #[serde(default)]
+    pub thumbnail_format: ThumbnailFormat,
+
+    /// Photos bigger than this in bytes are not decoded for thumbnails.
+    #[serde(default = "default_thumbnail_max_source_size")]
+    pub thumbnail_max_source_size: i64,
+
+    /// `Cache-Control` of asset responses. Revalidation is cheap thanks to ETags.
+    #[serde(default = "default_cache_control")]
+    pub cache_control: String,
+
+    #[serde(default)]
+    pub photo_delivery: PhotoDelivery,
+
+    #[serde(default = "default_presigned_url_expiry_secs")]
+    pub presigned_url_expiry_secs: u64,
+
+    /// Lets browsers upload straight to the storage with presigned URLs. The bucket must allow
+    /// PUT from the public URL with CORS.
+    #[serde(default)]
+    pub direct_upload: bool,
+
+    /// Biggest photo in bytes which can be uploaded directly.
+    #[serde(default = "default_max_upload_size")]
+    pub max_upload_size: i64,
+
+    /// How long deleted photos stay in the trash before they are purged for good. Zero keeps them
+    /// until they are purged by hand.
+    #[serde(default = "default_trash_retention_secs")]
+    pub trash_retention_secs: u64,
 }
 
 impl Config {
     pub fn try_from_env() -> Result<Self> {
         Ok(envy::from_env()?)
     }
+
+    /// Finds the highest role granted to any of the emails.
+    pub fn role_of(&self, emails: &[String]) -> Option<Role> {
+        let allowed = |allowed_emails: &[String]| {
+            allowed_emails
+                .iter()
+                .any(|allowed_email| emails.contains(allowed_email))
+        };
+        if allowed(&self.admin_emails) || allowed(&self.allowed_emails) {
+            Some(Role::Admin)
+        } else if allowed(&self.uploader_emails) {
+            Some(Role::Uploader)
+        } else if allowed(&self.viewer_emails) {
+            Some(Role::Viewer)
+        } else {
+            None
+        }
+    }
 }
@@ -0,0 +1,170 @@
+use std::collections::HashSet;
+
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+    routing, Json, Router,
+};
+use http::{header::ETAG, HeaderMap, HeaderValue};
+use itertools::Itertools;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    config::Role,
+    s3,
+    storage::{PutCondition, StorageError},
+    types::{
+        album::{AlbumCreationRequest, AlbumPhotosRequest, AlbumUpdateRequest, AlbumWithId},
+        error::Error,
+    },
+};
+
+use super::{auth::User, put_condition_from_headers, validate_name, AppState, ResponseResult};
+
+pub(super) fn create_album_router() -> Router<AppState> {
+    Router::new()
+        .route("/", routing::get(handle_get_albums).post(handle_post_album))
+        .route(
+            "/:id",
+            routing::get(handle_get_album)
+                .put(handle_put_album)
+                .delete(handle_delete_album),
+        )
+        .route("/:id/photos", routing::put(handle_put_album_photos))
+}
+
+fn map_album_error(error: anyhow::Error) -> Error {
+    match error.downcast_ref::<StorageError>() {
+        Some(StorageError::NotFound) => Error::AlbumNotFound,
+        Some(StorageError::PreconditionFailed) => Error::PreconditionFailed,
+        _ => Error::S3(error),
+    }
+}
+
+/// No album could have been created with an ID which is not a valid name.
+fn validate_id(id: &str) -> Result<(), Error> {
+    validate_name(id).map_err(|_| Error::AlbumNotFound)
+}
+
+async fn handle_get_albums(
+    _user: User,
+    State(state): State<AppState>,
+) -> ResponseResult<Json<Vec<AlbumWithId>>> {
+    let mut albums = s3::list_albums(&*state.storage).await.map_err(Error::S3)?;
+    albums.sort_by_key(|album| std::cmp::Reverse(album.album.created_at));
+    Ok(Json(albums))
+}
+
+#[derive(Serialize)]
+struct PostAlbumResp {
+    id: String,
+}
+
+async fn handle_post_album(
+    user: User,
+    State(state): State<AppState>,
+    Json(req): Json<AlbumCreationRequest>,
+) -> ResponseResult<Json<PostAlbumResp>> {
+    user.require(Role::Uploader)?;
+    let id = Uuid::new_v4().to_string();
+    let album = req.create(user.primary_email);
+    s3::upload_album(&*state.storage, &id, &album, PutCondition::IfAbsent)
+        .await
+        .map_err(Error::S3)?;
+    Ok(Json(PostAlbumResp { id }))
+}
+
+async fn handle_get_album(
+    _user: User,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> ResponseResult<Response> {
+    validate_id(&id)?;
+    let (album, e_tag) = s3::read_album(&*state.storage, &id)
+        .await
+        .map_err(map_album_error)?;
+    let mut resp = Json(album.with_id(id)).into_response();
+    if let Some(e_tag) = e_tag.and_then(|e_tag| HeaderValue::from_str(&e_tag).ok()) {
+        resp.headers_mut().insert(ETAG, e_tag);
+    }
+    Ok(resp)
+}
+
+async fn handle_put_album(
+    user: User,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(req): Json<AlbumUpdateRequest>,
+) -> ResponseResult<()> {
+    validate_id(&id)?;
+    let (album, e_tag) = s3::read_album(&*state.storage, &id)
+        .await
+        .map_err(map_album_error)?;
+    user.require_owner(&album.owner_email)?;
+    let album = req.update(album);
+    if let Some(cover) = &album.cover {
+        if !album.photos.contains(cover) {
+            return Err(Error::InvalidAlbum.into());
+        }
+    }
+    let condition = put_condition_from_headers(&headers, e_tag)?;
+    s3::upload_album(&*state.storage, &id, &album, condition)
+        .await
+        .map_err(map_album_error)?;
+    Ok(())
+}
+
+async fn handle_put_album_photos(
+    user: User,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(req): Json<AlbumPhotosRequest>,
+) -> ResponseResult<()> {
+    validate_id(&id)?;
+    let (album, e_tag) = s3::read_album(&*state.storage, &id)
+        .await
+        .map_err(map_album_error)?;
+    user.require_owner(&album.owner_email)?;
+    if !req.photos.iter().all_unique() {
+        return Err(Error::InvalidAlbum.into());
+    }
+    let metadatas = state.list_metadatas().await.map_err(Error::S3)?;
+    let names = metadatas
+        .iter()
+        .map(|metadata| metadata.name.as_str())
+        .collect::<HashSet<_>>();
+    // Photos already in the album may be in the trash, and are kept for when they are restored.
+    if !req
+        .photos
+        .iter()
+        .filter(|photo| !album.photos.contains(photo))
+        .all(|photo| names.contains(photo.as_str()))
+    {
+        return Err(Error::PhotoNotFound.into());
+    }
+    let album = req.update(album);
+    let condition = put_condition_from_headers(&headers, e_tag)?;
+    s3::upload_album(&*state.storage, &id, &album, condition)
+        .await
+        .map_err(map_album_error)?;
+    Ok(())
+}
+
+async fn handle_delete_album(
+    user: User,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> ResponseResult<()> {
+    validate_id(&id)?;
+    let (album, _) = s3::read_album(&*state.storage, &id)
+        .await
+        .map_err(map_album_error)?;
+    user.require_owner(&album.owner_email)?;
+    s3::delete_album(&*state.storage, &id)
+        .await
+        .map_err(Error::S3)?;
+    Ok(())
+}
@@ -1,23 +1,35 @@
 use std::collections::BTreeMap;
 
 use axum::{
+    body::StreamBody,
     extract::{Path, Query, RawBody, State},
+    response::{IntoResponse, Response},
     routing, Json, Router,
 };
-use itertools::Itertools;
-use serde::Deserialize;
+use chrono::{DateTime, Utc};
+use http::{
+    header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    HeaderMap,
+};
+use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
-use simsearch::{SearchOptions, SimSearch};
+use uuid::Uuid;
 
 use crate::{
-    s3::{self, list_metadatas},
+    archive,
+    config::{Role, CONFIG},
+    photo, s3, search,
+    storage::StorageError,
     types::{
-        asset::{MetadataCreationRequest, MetadataUpdateRequest, MetadataWithName},
+        asset::{
+            MetadataCreationRequest, MetadataUpdateRequest, MetadataWithName, SortKey, SortOrder,
+        },
         error::Error,
+        history::{HistoryEntry, HistoryRevision, RevertRequest},
     },
 };
 
-use super::{auth::User, AppState, ResponseResult};
+use super::{auth::User, put_condition_from_headers, validate_name, AppState, ResponseResult};
 
 pub(super) fn create_api_router() -> Router<AppState> {
     Router::new()
@@ -28,6 +40,22 @@ pub(super) fn create_api_router() -> Router<AppState> {
                 .put(handle_put_photo)
                 .delete(handle_delete_photo),
         )
+        .route(
+            "/photo/:name/upload-url",
+            routing::post(handle_post_photo_upload_url),
+        )
+        .route(
+            "/photo/:name/commit",
+            routing::post(handle_post_photo_commit),
+        )
+        .route(
+            "/photo/:name/history",
+            routing::get(handle_get_photo_history),
+        )
+        .route(
+            "/photo/:name/revert",
+            routing::post(handle_post_photo_revert),
+        )
         .route(
             "/tags-with-sample",
             routing::get(handle_get_tags_with_sample),
@@ -37,58 +65,282 @@ pub(super) fn create_api_router() -> Router<AppState> {
             "/metadatas-by-tag",
             routing::get(handle_get_metadatas_by_tag),
         )
-        .route("/search", routing::post(handle_post_search))
+        .route("/search", routing::get(handle_get_search))
+        .route("/export", routing::get(handle_get_export))
+        .nest("/album", super::album::create_album_router())
+        .nest("/share", super::share::create_share_router())
+        .nest("/trash", super::trash::create_trash_router())
+        .route(
+            "/admin/consistency",
+            routing::get(handle_get_admin_consistency),
+        )
 }
 
 async fn handle_get_user(user: User) -> Json<User> {
     user.into()
 }
 
+#[derive(Deserialize)]
+struct PostPhotoReq {
+    #[serde(default)]
+    overwrite: bool,
+}
+
 async fn handle_post_photo(
     user: User,
     Path(name): Path<String>,
     Query(metadata_creation_req): Query<MetadataCreationRequest>,
+    Query(req): Query<PostPhotoReq>,
     State(state): State<AppState>,
     RawBody(body): RawBody,
 ) -> ResponseResult<()> {
+    user.require(Role::Uploader)?;
+    if req.overwrite {
+        user.require(Role::Admin)?;
+    }
+    validate_name(&name)?;
+    let (content_type, body) = photo::sniff_body(body).await.map_err(Error::S3)?;
+    let content_type = content_type.ok_or(Error::UnsupportedContentType)?;
     let metadata = metadata_creation_req.create(user.primary_email);
-    s3::upload_photo(&state.s3_client, &name, &metadata, body)
+    let metadata = s3::upload_photo(
+        &*state.storage,
+        &name,
+        metadata,
+        body,
+        content_type,
+        req.overwrite,
+    )
+    .await
+    .map_err(|error| match error.downcast_ref::<StorageError>() {
+        Some(StorageError::PreconditionFailed) => Error::PhotoAlreadyExists,
+        _ => Error::S3(error),
+    })?;
+    if let Some(metadata_index) = &state.metadata_index {
+        metadata_index.insert(name, metadata);
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PostPhotoUploadUrlReq {
+    content_type: String,
+    size: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PostPhotoUploadUrlResp {
+    method: &'static str,
+    url: String,
+    headers: BTreeMap<&'static str, String>,
+    /// To be given to [`handle_post_photo_commit`].
+    upload_id: String,
+}
+
+/// First step of a direct upload. The browser PUTs the photo to the returned URL and then calls
+/// [`handle_post_photo_commit`] with the upload ID.
+async fn handle_post_photo_upload_url(
+    user: User,
+    Path(name): Path<String>,
+    Query(query): Query<PostPhotoReq>,
+    State(state): State<AppState>,
+    Json(req): Json<PostPhotoUploadUrlReq>,
+) -> ResponseResult<Json<PostPhotoUploadUrlResp>> {
+    user.require(Role::Uploader)?;
+    if query.overwrite {
+        user.require(Role::Admin)?;
+    }
+    validate_name(&name)?;
+    if !CONFIG.direct_upload {
+        return Err(Error::DirectUploadUnavailable.into());
+    }
+    if req.size <= 0 {
+        return Err(Error::InvalidPhotoSize.into());
+    }
+    if req.size > CONFIG.max_upload_size {
+        return Err(Error::PhotoTooLarge.into());
+    }
+    if !photo::is_supported_content_type(&req.content_type) {
+        return Err(Error::UnsupportedContentType.into());
+    }
+    // Fails early rather than after the upload. The commit checks again on condition.
+    if !query.overwrite && s3::head_photo(&*state.storage, &name).await.is_ok() {
+        return Err(Error::PhotoAlreadyExists.into());
+    }
+
+    let upload_id = Uuid::new_v4().simple().to_string();
+    let url = s3::presign_upload(&*state.storage, &upload_id, &req.content_type, req.size)
+        .await
+        .map_err(Error::S3)?
+        .ok_or(Error::DirectUploadUnavailable)?;
+    Ok(Json(PostPhotoUploadUrlResp {
+        method: "PUT",
+        url,
+        headers: BTreeMap::from([("Content-Type", req.content_type)]),
+        upload_id,
+    }))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PostPhotoCommitReq {
+    upload_id: String,
+    #[serde(default)]
+    overwrite: bool,
+}
+
+/// Second step of a direct upload, which moves the uploaded photo in place and writes its
+/// metadata.
+async fn handle_post_photo_commit(
+    user: User,
+    Path(name): Path<String>,
+    Query(metadata_creation_req): Query<MetadataCreationRequest>,
+    Query(req): Query<PostPhotoCommitReq>,
+    State(state): State<AppState>,
+) -> ResponseResult<()> {
+    user.require(Role::Uploader)?;
+    if req.overwrite {
+        user.require(Role::Admin)?;
+    }
+    validate_name(&name)?;
+    validate_name(&req.upload_id).map_err(|_| Error::PhotoNotFound)?;
+
+    let object = s3::head_upload(&*state.storage, &req.upload_id)
+        .await
+        .map_err(|error| match error.downcast_ref::<StorageError>() {
+            Some(StorageError::NotFound) => Error::PhotoNotFound,
+            _ => Error::S3(error),
+        })?;
+    if object.size > CONFIG.max_upload_size {
+        s3::delete_upload(&*state.storage, &req.upload_id)
+            .await
+            .map_err(Error::S3)?;
+        return Err(Error::PhotoTooLarge.into());
+    }
+    // The browser chose the stored content type, so it is checked against the actual bytes.
+    let content_type = match s3::sniff_upload(&*state.storage, &req.upload_id)
+        .await
+        .map_err(Error::S3)?
+    {
+        (Some(sniffed), Some(stored)) if sniffed == stored => sniffed,
+        _ => {
+            s3::delete_upload(&*state.storage, &req.upload_id)
+                .await
+                .map_err(Error::S3)?;
+            return Err(Error::UnsupportedContentType.into());
+        }
+    };
+    s3::commit_upload(&*state.storage, &req.upload_id, &name, req.overwrite)
+        .await
+        .map_err(|error| match error.downcast_ref::<StorageError>() {
+            Some(StorageError::PreconditionFailed) => Error::PhotoAlreadyExists,
+            _ => Error::S3(error),
+        })?;
+
+    let metadata = metadata_creation_req.create(user.primary_email);
+    let metadata = s3::finish_upload(&*state.storage, &name, metadata, content_type)
         .await
         .map_err(Error::S3)?;
+    if let Some(metadata_index) = &state.metadata_index {
+        metadata_index.insert(name, metadata);
+    }
     Ok(())
 }
 
 async fn handle_put_photo(
-    _user: User,
+    user: User,
     Path(name): Path<String>,
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(req): Json<MetadataUpdateRequest>,
 ) -> ResponseResult<()> {
-    let resp = s3::get_metadata(&state.s3_client, &name)
+    validate_name(&name)?;
+    let (metadata, e_tag) = s3::read_metadata_with_e_tag(&*state.storage, &name)
         .await
         .map_err(Error::S3)?;
-    let body = resp
-        .body
-        .collect()
-        .await
-        .map_err(|e| Error::S3(e.into()))?
-        .into_bytes();
-    let metadata = serde_json::from_slice(&body).map_err(|e| Error::S3(e.into()))?;
+    user.require_modify(&metadata)?;
+    let before = metadata.clone();
     let metadata = req.update(metadata);
-    s3::upload_metadata(&state.s3_client, &name, &metadata)
+    let condition = put_condition_from_headers(&headers, e_tag)?;
+    s3::upload_metadata(&*state.storage, &name, &metadata, condition)
+        .await
+        .map_err(|error| match error.downcast_ref::<StorageError>() {
+            Some(StorageError::PreconditionFailed) => Error::PreconditionFailed,
+            _ => Error::S3(error),
+        })?;
+    let entry = HistoryEntry::between(user.primary_email, Some(&before), &metadata);
+    if !entry.is_empty() {
+        if let Err(error) = s3::append_history(&*state.storage, &name, entry).await {
+            tracing::warn!(%name, %error, "failed to append history");
+        }
+    }
+    if let Some(metadata_index) = &state.metadata_index {
+        metadata_index.insert(name, metadata);
+    }
+    Ok(())
+}
+
+async fn handle_get_photo_history(
+    _user: User,
+    Path(name): Path<String>,
+    State(state): State<AppState>,
+) -> ResponseResult<Json<Vec<HistoryRevision>>> {
+    validate_name(&name)?;
+    let (entries, _) = s3::read_history(&*state.storage, &name)
+        .await
+        .map_err(Error::S3)?;
+    let revisions = entries
+        .into_iter()
+        .enumerate()
+        .map(|(revision, entry)| HistoryRevision { revision, entry })
+        .collect();
+    Ok(Json(revisions))
+}
+
+async fn handle_post_photo_revert(
+    user: User,
+    Path(name): Path<String>,
+    State(state): State<AppState>,
+    Json(req): Json<RevertRequest>,
+) -> ResponseResult<()> {
+    validate_name(&name)?;
+    let metadata = s3::read_metadata(&*state.storage, &name)
         .await
         .map_err(Error::S3)?;
+    user.require_modify(&metadata)?;
+    let metadata = s3::revert_metadata(&*state.storage, &name, req.revision, user.primary_email)
+        .await
+        .map_err(|error| match error.downcast_ref::<StorageError>() {
+            Some(StorageError::PreconditionFailed) => Error::PreconditionFailed,
+            _ => Error::S3(error),
+        })?
+        .ok_or(Error::RevisionNotFound)?;
+    if let Some(metadata_index) = &state.metadata_index {
+        metadata_index.insert(name, metadata);
+    }
     Ok(())
 }
 
 async fn handle_delete_photo(
-    _user: User,
+    user: User,
     Path(name): Path<String>,
     State(state): State<AppState>,
 ) -> ResponseResult<()> {
-    s3::delete_photo(&state.s3_client, &name)
+    validate_name(&name)?;
+    let metadata = s3::read_metadata(&*state.storage, &name)
         .await
         .map_err(Error::S3)?;
+    user.require_modify(&metadata)?;
+    s3::trash_photo(&*state.storage, &name)
+        .await
+        .map_err(|error| match error.downcast_ref::<StorageError>() {
+            Some(StorageError::PreconditionFailed) => Error::PhotoAlreadyInTrash,
+            _ => Error::S3(error),
+        })?;
+    if let Some(metadata_index) = &state.metadata_index {
+        metadata_index.remove(&name);
+    }
     Ok(())
 }
 
@@ -113,6 +365,28 @@ impl Pagination {
     }
 }
 
+#[derive(Deserialize)]
+struct Sort {
+    #[serde(default)]
+    sort: SortKey,
+    #[serde(default)]
+    order: SortOrder,
+}
+
+impl Sort {
+    fn apply(
+        &self,
+        metadatas: impl IntoIterator<Item = MetadataWithName>,
+    ) -> Vec<MetadataWithName> {
+        let mut metadatas = metadatas.into_iter().collect::<Vec<_>>();
+        metadatas.sort_by(|a, b| match self.order {
+            SortOrder::Asc => a.cmp_by(b, self.sort),
+            SortOrder::Desc => b.cmp_by(a, self.sort),
+        });
+        metadatas
+    }
+}
+
 #[derive(Deserialize)]
 struct GetTagsWithSampleReq {
     #[serde(flatten)]
@@ -124,7 +398,7 @@ async fn handle_get_tags_with_sample(
     Query(req): Query<GetTagsWithSampleReq>,
     State(state): State<AppState>,
 ) -> ResponseResult<Json<BTreeMap<String, MetadataWithName>>> {
-    let metadatas = list_metadatas(&state.s3_client).await.map_err(Error::S3)?;
+    let metadatas = state.list_metadatas().await.map_err(Error::S3)?;
     let mut tags_with_sample = BTreeMap::new();
     for metadata in metadatas {
         for tag in &metadata.metadata.tags {
@@ -146,6 +420,8 @@ async fn handle_get_tags_with_sample(
 struct GetMetadatasReq {
     #[serde(flatten)]
     pagination: Pagination,
+    #[serde(flatten)]
+    sort: Sort,
 }
 
 async fn handle_get_metadatas(
@@ -153,8 +429,9 @@ async fn handle_get_metadatas(
     Query(req): Query<GetMetadatasReq>,
     State(state): State<AppState>,
 ) -> ResponseResult<Json<Vec<MetadataWithName>>> {
-    let metadatas = list_metadatas(&state.s3_client).await.map_err(Error::S3)?;
-    let metadatas = req.pagination.apply(metadatas.into_iter().rev()).collect();
+    let metadatas = state.list_metadatas().await.map_err(Error::S3)?;
+    let metadatas = req.sort.apply(metadatas);
+    let metadatas = req.pagination.apply(metadatas.into_iter()).collect();
     Ok(Json(metadatas))
 }
 
@@ -162,6 +439,8 @@ async fn handle_get_metadatas(
 struct GetMetadatasByTagReq {
     #[serde(flatten)]
     pagination: Pagination,
+    #[serde(flatten)]
+    sort: Sort,
     tag: String,
 }
 
@@ -170,45 +449,93 @@ async fn handle_get_metadatas_by_tag(
     Query(req): Query<GetMetadatasByTagReq>,
     State(state): State<AppState>,
 ) -> ResponseResult<Json<Vec<MetadataWithName>>> {
-    let metadatas = list_metadatas(&state.s3_client).await.map_err(Error::S3)?;
-    let metadatas = metadatas
-        .into_iter()
-        .filter(|metadata| metadata.metadata.tags.contains(&req.tag));
-    let metadatas = req.pagination.apply(metadatas.rev()).collect();
+    let metadatas = state.list_metadatas().await.map_err(Error::S3)?;
+    let metadatas = req.sort.apply(
+        metadatas
+            .into_iter()
+            .filter(|metadata| metadata.metadata.tags.contains(&req.tag)),
+    );
+    let metadatas = req.pagination.apply(metadatas.into_iter()).collect();
     Ok(Json(metadatas))
 }
 
 #[derive(Deserialize)]
-struct PostSearchReq {
-    token: String,
+struct GetSearchReq {
+    #[serde(flatten)]
+    pagination: Pagination,
+    #[serde(flatten)]
+    sort: Sort,
+    query: String,
 }
 
-async fn handle_post_search(
+/// Filters metadatas by a query in the language of [`search`].
+async fn handle_get_search(
     _user: User,
+    Query(req): Query<GetSearchReq>,
     State(state): State<AppState>,
-    Json(req): Json<PostSearchReq>,
 ) -> ResponseResult<Json<Vec<MetadataWithName>>> {
-    let metadatas = list_metadatas(&state.s3_client).await.map_err(Error::S3)?;
+    let query = req
+        .query
+        .parse::<search::Query>()
+        .map_err(Error::InvalidQuery)?;
+    let metadatas = state.list_metadatas().await.map_err(Error::S3)?;
+    let metadatas = req.sort.apply(
+        metadatas
+            .into_iter()
+            .filter(|metadata| query.matches(metadata)),
+    );
+    let metadatas = req.pagination.apply(metadatas.into_iter()).collect();
+    Ok(Json(metadatas))
+}
 
-    let search_options = SearchOptions::new()
-        .stop_words(vec!["-".to_string(), "_".to_string(), ".".to_string()])
-        .threshold(0.6);
-    let mut search = SimSearch::new_with(search_options);
+#[derive(Deserialize)]
+struct GetExportReq {
+    tag: Option<String>,
+    /// Bounds on the capture time, falling back to the upload time.
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+}
 
-    for metadata in metadatas {
-        search.insert(
-            metadata.clone(),
-            &format!(
-                "{}\n{}\n{}",
-                metadata.name,
-                metadata.metadata.description,
-                metadata.metadata.tags.iter().join("\n")
-            ),
-        );
-    }
+async fn handle_get_export(
+    _user: User,
+    Query(req): Query<GetExportReq>,
+    State(state): State<AppState>,
+) -> ResponseResult<Response> {
+    let metadatas = state.list_metadatas().await.map_err(Error::S3)?;
+    let metadatas = metadatas
+        .into_iter()
+        .filter(|metadata| match &req.tag {
+            Some(tag) => metadata.metadata.tags.contains(tag),
+            None => true,
+        })
+        .filter(|metadata| {
+            let at = metadata.metadata.taken_or_created_at();
+            req.from.map(|from| from <= at).unwrap_or(true)
+                && req.to.map(|to| at < to).unwrap_or(true)
+        })
+        .collect();
 
-    let mut metadatas = search.search(&req.token);
-    metadatas.truncate(30);
+    let body = StreamBody::new(archive::export(state.storage.clone(), metadatas));
+    Ok((
+        [
+            (CONTENT_TYPE, "application/x-tar"),
+            (
+                CONTENT_DISPOSITION,
+                "attachment; filename=\"cheph-export.tar\"",
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
 
-    Ok(Json(metadatas))
+async fn handle_get_admin_consistency(
+    user: User,
+    State(state): State<AppState>,
+) -> ResponseResult<Json<s3::ConsistencyReport>> {
+    user.require(Role::Admin)?;
+    let report = s3::check_consistency(&*state.storage)
+        .await
+        .map_err(Error::S3)?;
+    Ok(Json(report))
 }
@@ -1,17 +1,27 @@
-use aws_sdk_s3::{output::GetObjectOutput, types::ByteStream};
 use axum::{
     body::StreamBody,
-    extract::{Path, State},
+    extract::{Path, Query, State},
+    response::{IntoResponse, Response},
     routing, Router,
 };
+use chrono::{DateTime, Utc};
 use http::{
-    header::{CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE},
-    HeaderMap,
+    header::{
+        ACCEPT_RANGES, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE,
+        CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, LOCATION, RANGE,
+    },
+    HeaderMap, HeaderValue, StatusCode,
 };
+use serde::Deserialize;
 
-use crate::{s3, types::error::Error};
+use crate::{
+    config::{PhotoDelivery, CONFIG},
+    photo, s3,
+    storage::{GetOptions, Object, StorageError},
+    types::error::Error,
+};
 
-use super::{auth::User, AppState, ResponseResult};
+use super::{auth::User, validate_name, AppState, ResponseResult};
 
 pub(super) fn create_asset_router() -> Router<AppState> {
     Router::new()
@@ -19,41 +29,142 @@ pub(super) fn create_asset_router() -> Router<AppState> {
         .route("/metadata/:name", routing::get(handle_get_metadata))
 }
 
-fn make_response_from_s3_output(output: GetObjectOutput) -> (HeaderMap, StreamBody<ByteStream>) {
+fn get_options_from_headers(headers: &HeaderMap) -> GetOptions {
+    let header = |name| {
+        headers
+            .get(name)
+            .and_then(|value: &HeaderValue| value.to_str().ok())
+            .map(str::to_string)
+    };
+    GetOptions {
+        range: header(RANGE),
+        if_none_match: header(IF_NONE_MATCH),
+        if_modified_since: header(IF_MODIFIED_SINCE)
+            .and_then(|since| DateTime::parse_from_rfc2822(&since).ok())
+            .map(|since| since.with_timezone(&Utc)),
+    }
+}
+
+fn make_response_from_object(result: anyhow::Result<Object>) -> ResponseResult<Response> {
     let mut headers = HeaderMap::new();
-    headers.insert(CONTENT_LENGTH, output.content_length().into());
-    if let Some(content_type) = output.content_type() {
+    if let Ok(cache_control) = CONFIG.cache_control.parse() {
+        headers.insert(CACHE_CONTROL, cache_control);
+    }
+
+    let object = match result {
+        Ok(object) => object,
+        Err(error) => {
+            return match error.downcast_ref::<StorageError>() {
+                Some(StorageError::NotModified) => {
+                    Ok((StatusCode::NOT_MODIFIED, headers).into_response())
+                }
+                Some(StorageError::RangeNotSatisfiable) => Err(Error::RangeNotSatisfiable.into()),
+                _ => Err(Error::S3(error).into()),
+            }
+        }
+    };
+
+    headers.insert(CONTENT_LENGTH, object.content_length.into());
+    headers.insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    if let Some(content_type) = object.content_type {
         if let Ok(content_type) = content_type.parse() {
             headers.insert(CONTENT_TYPE, content_type);
         }
     }
-    if let Some(content_encoding) = output.content_encoding() {
+    if let Some(content_encoding) = object.content_encoding {
         if let Ok(content_encoding) = content_encoding.parse() {
             headers.insert(CONTENT_ENCODING, content_encoding);
         }
     }
+    if let Some(e_tag) = object.e_tag {
+        if let Ok(e_tag) = e_tag.parse() {
+            headers.insert(ETAG, e_tag);
+        }
+    }
+    if let Some(last_modified) = object.last_modified {
+        let last_modified = last_modified
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string();
+        if let Ok(last_modified) = last_modified.parse() {
+            headers.insert(LAST_MODIFIED, last_modified);
+        }
+    }
+    let status_code = match object.content_range {
+        Some(content_range) => {
+            if let Ok(content_range) = content_range.parse() {
+                headers.insert(CONTENT_RANGE, content_range);
+            }
+            StatusCode::PARTIAL_CONTENT
+        }
+        None => StatusCode::OK,
+    };
 
-    (headers, StreamBody::new(output.body))
+    Ok((status_code, headers, StreamBody::new(object.body)).into_response())
+}
+
+#[derive(Deserialize)]
+struct GetPhotoReq {
+    #[serde(default)]
+    size: Option<u32>,
 }
 
 async fn handle_get_photo(
     _user: User,
     State(state): State<AppState>,
     Path(name): Path<String>,
-) -> ResponseResult<(HeaderMap, StreamBody<ByteStream>)> {
-    let output = s3::get_photo(&state.s3_client, &name)
-        .await
-        .map_err(Error::S3)?;
-    Ok(make_response_from_s3_output(output))
+    Query(req): Query<GetPhotoReq>,
+    headers: HeaderMap,
+) -> ResponseResult<Response> {
+    validate_name(&name)?;
+    photo_response(&state, &name, req.size, &headers).await
+}
+
+/// Serves a photo, or its variant closest to `size`, as configured by `PHOTO_DELIVERY`.
+pub(super) async fn photo_response(
+    state: &AppState,
+    name: &str,
+    size: Option<u32>,
+    headers: &HeaderMap,
+) -> ResponseResult<Response> {
+    let size = size.and_then(photo::pick_size);
+
+    if CONFIG.photo_delivery == PhotoDelivery::Redirect {
+        let url = s3::presign_photo(&*state.storage, size, name)
+            .await
+            .map_err(Error::S3)?;
+        if let Some(url) = url {
+            return Ok((StatusCode::FOUND, [(LOCATION, url)]).into_response());
+        }
+    }
+
+    let options = get_options_from_headers(headers);
+
+    if let Some(size) = size {
+        match s3::get_thumbnail(&*state.storage, size, name, options.clone()).await {
+            Ok(object) => return make_response_from_object(Ok(object)),
+            Err(error)
+                if matches!(
+                    error.downcast_ref::<StorageError>(),
+                    Some(StorageError::NotModified | StorageError::RangeNotSatisfiable)
+                ) =>
+            {
+                return make_response_from_object(Err(error))
+            }
+            // Photos without variants, such as videos, are served as is.
+            Err(_) => {}
+        }
+    }
+
+    make_response_from_object(s3::get_photo(&*state.storage, name, options).await)
 }
 
 async fn handle_get_metadata(
     _user: User,
     State(state): State<AppState>,
     Path(name): Path<String>,
-) -> ResponseResult<(HeaderMap, StreamBody<ByteStream>)> {
-    let output = s3::get_metadata(&state.s3_client, &name)
-        .await
-        .map_err(Error::S3)?;
-    Ok(make_response_from_s3_output(output))
+    headers: HeaderMap,
+) -> ResponseResult<Response> {
+    validate_name(&name)?;
+    let options = get_options_from_headers(&headers);
+    make_response_from_object(s3::get_metadata(&*state.storage, &name, options).await)
 }
@@ -0,0 +1,107 @@
+use anyhow::Result;
+use axum::async_trait;
+use http::header::ACCEPT;
+use oauth2::{
+    basic::BasicClient, reqwest::async_http_client, AuthUrl, AuthorizationCode, ClientId,
+    ClientSecret, CsrfToken, Scope, TokenResponse, TokenUrl,
+};
+use serde::Deserialize;
+
+use crate::types::error::Error;
+
+use super::{Authorization, OAuthState, Provider, VerifiedEmails};
+
+pub(super) struct GitHubProvider {
+    client: BasicClient,
+}
+
+impl GitHubProvider {
+    pub fn new(client_id: String, client_secret: String) -> Self {
+        let client = BasicClient::new(
+            ClientId::new(client_id),
+            Some(ClientSecret::new(client_secret)),
+            AuthUrl::new("https://github.com/login/oauth/authorize".to_string()).unwrap(),
+            Some(TokenUrl::new("https://github.com/login/oauth/access_token".to_string()).unwrap()),
+        )
+        .set_redirect_uri(super::redirect_url());
+        Self { client }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct GitHubEmailsResp {
+    email: String,
+    verified: bool,
+    primary: bool,
+}
+
+#[async_trait]
+impl Provider for GitHubProvider {
+    fn id(&self) -> &str {
+        "github"
+    }
+
+    fn display_name(&self) -> &str {
+        "GitHub"
+    }
+
+    fn authorize(&self) -> Authorization {
+        let (url, csrf_token) = self
+            .client
+            .authorize_url(CsrfToken::new_random)
+            .add_scope(Scope::new("user:email".to_string()))
+            .url();
+        Authorization {
+            url,
+            state: csrf_token.secret().clone(),
+            nonce: None,
+            pkce_verifier: None,
+        }
+    }
+
+    async fn exchange(
+        &self,
+        code: String,
+        _oauth_state: &OAuthState,
+        http_client: &reqwest::Client,
+    ) -> Result<VerifiedEmails> {
+        let token = self
+            .client
+            .exchange_code(AuthorizationCode::new(code))
+            .request_async(async_http_client)
+            .await
+            .map_err(|_| Error::Authorize)?;
+
+        let resp: Vec<GitHubEmailsResp> = http_client
+            .get("https://api.github.com/user/emails")
+            .bearer_auth(token.access_token().secret())
+            .header(ACCEPT, "application/vnd.github+json")
+            .header("X-GitHub-Api-Version", "2022-11-28")
+            .send()
+            .await
+            .map_err(|_| Error::Authorize)?
+            .json()
+            .await
+            .map_err(|_| Error::Authorize)?;
+
+        let mut primary_email = None;
+        let mut emails = Vec::with_capacity(resp.len());
+        for email in resp {
+            if email.primary && primary_email.is_none() {
+                primary_email = Some(email.email.clone());
+            }
+            if email.verified {
+                emails.push(email.email);
+            }
+        }
+        if emails.is_empty() {
+            return Err(Error::Authorize.into());
+        }
+        let primary_email = primary_email.unwrap_or_else(|| emails[0].clone());
+
+        Ok(VerifiedEmails {
+            primary_email,
+            emails,
+        })
+    }
+}
@@ -0,0 +1,328 @@
+mod github;
+mod oidc;
+
+use anyhow::Result;
+use axum::{
+    async_trait,
+    extract::{rejection::TypedHeaderRejectionReason, FromRequestParts, Path, Query, State},
+    headers,
+    http::request::Parts,
+    response::Redirect,
+    routing, Json, RequestPartsExt, Router, TypedHeader,
+};
+use chrono::{Duration, Utc};
+use http::{
+    header::{self, SET_COOKIE},
+    HeaderMap,
+};
+use jsonwebtoken::{decode, encode, Validation};
+use oauth2::RedirectUrl;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{
+    config::{Role, CONFIG},
+    types::{asset::Metadata, error::Error},
+};
+
+use self::{github::GitHubProvider, oidc::OidcProvider};
+
+use super::{AppState, ResponseError, ResponseResult};
+
+static COOKIE_NAME: &str = "SESSION";
+static OAUTH_STATE_COOKIE_NAME: &str = "OAUTH_STATE";
+
+/// Every provider redirects back to the same URL, telling itself apart by the OAuth state cookie.
+fn redirect_url() -> RedirectUrl {
+    RedirectUrl::from_url(CONFIG.public_url.join("./auth/authorized").unwrap())
+}
+
+/// Path of the OAuth state cookie, which covers the redirect URL under a prefix of `PUBLIC_URL`.
+fn oauth_state_cookie_path() -> String {
+    let url = CONFIG.public_url.join("./auth/").unwrap();
+    url.path().trim_end_matches('/').to_string()
+}
+
+/// Secrets of an ongoing authorization, which are kept in the OAuth state cookie.
+pub(super) struct Authorization {
+    url: Url,
+    state: String,
+    nonce: Option<String>,
+    pkce_verifier: Option<String>,
+}
+
+pub(super) struct VerifiedEmails {
+    primary_email: String,
+    emails: Vec<String>,
+}
+
+#[async_trait]
+pub(super) trait Provider: Send + Sync {
+    fn id(&self) -> &str;
+
+    fn display_name(&self) -> &str;
+
+    fn authorize(&self) -> Authorization;
+
+    async fn exchange(
+        &self,
+        code: String,
+        oauth_state: &OAuthState,
+        http_client: &reqwest::Client,
+    ) -> Result<VerifiedEmails>;
+}
+
+/// Creates every provider enabled in the config. OpenID Connect providers which fail discovery are
+/// left out.
+pub(super) async fn create_providers() -> Vec<Box<dyn Provider>> {
+    let mut providers = Vec::<Box<dyn Provider>>::new();
+
+    if let (Some(client_id), Some(client_secret)) =
+        (&CONFIG.github_client_id, &CONFIG.github_client_secret)
+    {
+        providers.push(Box::new(GitHubProvider::new(
+            client_id.clone(),
+            client_secret.clone(),
+        )));
+    }
+
+    for config in &CONFIG.oidc_providers {
+        match OidcProvider::discover(config).await {
+            Ok(provider) => providers.push(Box::new(provider)),
+            Err(error) => {
+                tracing::error!(provider = %config.id, %error, "failed to discover OpenID Connect provider")
+            }
+        }
+    }
+
+    providers
+}
+
+pub(super) fn create_auth_router() -> Router<AppState> {
+    Router::new()
+        .route("/providers", routing::get(handle_get_providers))
+        .route("/login/:provider", routing::get(handle_get_login))
+        .route("/authorized", routing::get(handle_get_authorized))
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct User {
+    pub primary_email: String,
+    pub emails: Vec<String>,
+    pub exp: i64,
+    /// Looked up from the config on every request rather than trusted from the session.
+    #[serde(default, skip_deserializing)]
+    pub role: Role,
+}
+
+impl User {
+    pub fn require(&self, role: Role) -> Result<(), Error> {
+        if self.role >= role {
+            Ok(())
+        } else {
+            Err(Error::PermissionDenied)
+        }
+    }
+
+    /// Admins can modify everything, and uploaders only what they own.
+    pub fn require_owner(&self, owner_email: &str) -> Result<(), Error> {
+        match self.role {
+            Role::Admin => Ok(()),
+            Role::Uploader if self.emails.iter().any(|email| email == owner_email) => Ok(()),
+            _ => Err(Error::PermissionDenied),
+        }
+    }
+
+    pub fn require_modify(&self, metadata: &Metadata) -> Result<(), Error> {
+        self.require_owner(&metadata.creator_email)
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for User
+where
+    S: Send + Sync,
+{
+    type Rejection = ResponseError;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        let cookies = parts
+            .extract::<TypedHeader<headers::Cookie>>()
+            .await
+            .map_err(|e| match *e.name() {
+                header::COOKIE => match e.reason() {
+                    TypedHeaderRejectionReason::Missing => Error::UserNotAuthorized,
+                    _ => Error::Authorize,
+                },
+                _ => Error::Authorize,
+            })?;
+        let session_cookie = cookies.get(COOKIE_NAME).ok_or(Error::UserNotAuthorized)?;
+
+        let mut jwt_validation = Validation::default();
+        jwt_validation.validate_exp = true;
+        let user_data = decode::<User>(session_cookie, &CONFIG.jwt_secret.1, &jwt_validation)
+            .map_err(|_| Error::UserNotAuthorized)?;
+        let mut user = user_data.claims;
+
+        user.role = CONFIG.role_of(&user.emails).ok_or(Error::UserNotAllowed)?;
+
+        Ok(user)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ProviderResp {
+    id: String,
+    display_name: String,
+}
+
+async fn handle_get_providers(State(state): State<AppState>) -> Json<Vec<ProviderResp>> {
+    let providers = state
+        .auth_providers
+        .iter()
+        .map(|provider| ProviderResp {
+            id: provider.id().to_string(),
+            display_name: provider.display_name().to_string(),
+        })
+        .collect();
+    Json(providers)
+}
+
+#[derive(Deserialize)]
+struct GetLoginReq {
+    #[serde(default)]
+    redirect: Option<String>,
+}
+
+/// Ongoing OAuth flow, signed and kept in a short-lived cookie.
+#[derive(Deserialize, Serialize)]
+pub(super) struct OAuthState {
+    provider: String,
+    state: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    nonce: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pkce_verifier: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    redirect: Option<String>,
+    exp: i64,
+}
+
+async fn handle_get_login(
+    State(state): State<AppState>,
+    Path(provider): Path<String>,
+    Query(req): Query<GetLoginReq>,
+) -> ResponseResult<(HeaderMap, Redirect)> {
+    let provider = state
+        .auth_providers
+        .iter()
+        .find(|p| p.id() == provider)
+        .ok_or(Error::ProviderNotFound)?;
+    let authorization = provider.authorize();
+
+    let oauth_state = OAuthState {
+        provider: provider.id().to_string(),
+        state: authorization.state,
+        nonce: authorization.nonce,
+        pkce_verifier: authorization.pkce_verifier,
+        redirect: req.redirect,
+        exp: (Utc::now() + Duration::minutes(10)).timestamp(),
+    };
+    let oauth_state = encode(&Default::default(), &oauth_state, &CONFIG.jwt_secret.0)
+        .map_err(|_| Error::Authorize)?;
+    let cookie = format!(
+        "{}={}; HttpOnly; SameSite=Lax; Path={}; Max-Age=600",
+        OAUTH_STATE_COOKIE_NAME,
+        oauth_state,
+        oauth_state_cookie_path()
+    );
+
+    let mut headers = HeaderMap::new();
+    headers.insert(SET_COOKIE, cookie.parse().unwrap());
+
+    Ok((headers, Redirect::to(authorization.url.as_ref())))
+}
+
+#[derive(Deserialize)]
+struct AuthRequest {
+    code: String,
+    state: String,
+}
+
+fn verify_oauth_state(cookies: Option<&headers::Cookie>, state: &str) -> Result<OAuthState, Error> {
+    let oauth_state = cookies
+        .and_then(|cookies| cookies.get(OAUTH_STATE_COOKIE_NAME))
+        .ok_or(Error::OAuthStateMismatch)?;
+
+    let mut jwt_validation = Validation::default();
+    jwt_validation.validate_exp = true;
+    let oauth_state = decode::<OAuthState>(oauth_state, &CONFIG.jwt_secret.1, &jwt_validation)
+        .map_err(|_| Error::OAuthStateMismatch)?
+        .claims;
+
+    if oauth_state.state != state {
+        return Err(Error::OAuthStateMismatch);
+    }
+    Ok(oauth_state)
+}
+
+async fn handle_get_authorized(
+    Query(req): Query<AuthRequest>,
+    State(state): State<AppState>,
+    cookies: Option<TypedHeader<headers::Cookie>>,
+) -> ResponseResult<(HeaderMap, Redirect)> {
+    let oauth_state = verify_oauth_state(cookies.as_deref(), &req.state)?;
+    let provider = state
+        .auth_providers
+        .iter()
+        .find(|p| p.id() == oauth_state.provider)
+        .ok_or(Error::ProviderNotFound)?;
+
+    let emails = provider
+        .exchange(req.code, &oauth_state, &state.http_client)
+        .await?;
+
+    Ok(authorized(emails, oauth_state.redirect)?)
+}
+
+fn authorized(
+    VerifiedEmails {
+        primary_email,
+        emails,
+    }: VerifiedEmails,
+    redirect: Option<String>,
+) -> Result<(HeaderMap, Redirect)> {
+    let now = Utc::now();
+    let exp = (now + Duration::days(1)).timestamp();
+
+    let user = User {
+        role: CONFIG.role_of(&emails).unwrap_or_default(),
+        primary_email,
+        emails,
+        exp,
+    };
+
+    let session_token =
+        encode(&Default::default(), &user, &CONFIG.jwt_secret.0).map_err(|_| Error::Authorize)?;
+
+    let cookie = format!("{}={}; SameSite=Lax; Path=/", COOKIE_NAME, session_token);
+
+    let mut headers = HeaderMap::new();
+    headers.insert(SET_COOKIE, cookie.parse().unwrap());
+    headers.append(
+        SET_COOKIE,
+        format!(
+            "{}=; Path={}; Max-Age=0",
+            OAUTH_STATE_COOKIE_NAME,
+            oauth_state_cookie_path()
+        )
+        .parse()
+        .unwrap(),
+    );
+
+    let redirect = redirect.as_deref().unwrap_or("/");
+
+    Ok((headers, Redirect::to(redirect)))
+}
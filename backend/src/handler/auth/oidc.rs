@@ -0,0 +1,121 @@
+use anyhow::Result;
+use axum::async_trait;
+use openidconnect::{
+    core::{CoreAuthenticationFlow, CoreClient, CoreProviderMetadata, CoreUserInfoClaims},
+    reqwest::async_http_client,
+    AuthorizationCode, ClientId, ClientSecret, CsrfToken, IssuerUrl, Nonce, OAuth2TokenResponse,
+    PkceCodeChallenge, PkceCodeVerifier, Scope, TokenResponse,
+};
+
+use crate::{config::OidcProviderConfig, types::error::Error};
+
+use super::{Authorization, OAuthState, Provider, VerifiedEmails};
+
+/// Any OpenID Connect provider supporting discovery, such as Google or Keycloak.
+pub(super) struct OidcProvider {
+    id: String,
+    display_name: String,
+    client: CoreClient,
+}
+
+impl OidcProvider {
+    pub async fn discover(config: &OidcProviderConfig) -> Result<Self> {
+        let provider_metadata = CoreProviderMetadata::discover_async(
+            IssuerUrl::from_url(config.issuer_url.clone()),
+            async_http_client,
+        )
+        .await?;
+        let client = CoreClient::from_provider_metadata(
+            provider_metadata,
+            ClientId::new(config.client_id.clone()),
+            Some(ClientSecret::new(config.client_secret.clone())),
+        )
+        .set_redirect_uri(super::redirect_url());
+
+        Ok(Self {
+            id: config.id.clone(),
+            display_name: config
+                .display_name
+                .clone()
+                .unwrap_or_else(|| config.id.clone()),
+            client,
+        })
+    }
+}
+
+#[async_trait]
+impl Provider for OidcProvider {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn display_name(&self) -> &str {
+        &self.display_name
+    }
+
+    fn authorize(&self) -> Authorization {
+        let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+        let (url, csrf_token, nonce) = self
+            .client
+            .authorize_url(
+                CoreAuthenticationFlow::AuthorizationCode,
+                CsrfToken::new_random,
+                Nonce::new_random,
+            )
+            .add_scope(Scope::new("email".to_string()))
+            .set_pkce_challenge(pkce_challenge)
+            .url();
+        Authorization {
+            url,
+            state: csrf_token.secret().clone(),
+            nonce: Some(nonce.secret().clone()),
+            pkce_verifier: Some(pkce_verifier.secret().clone()),
+        }
+    }
+
+    async fn exchange(
+        &self,
+        code: String,
+        oauth_state: &OAuthState,
+        _http_client: &reqwest::Client,
+    ) -> Result<VerifiedEmails> {
+        let nonce = oauth_state.nonce.clone().ok_or(Error::Authorize)?;
+        let pkce_verifier = oauth_state.pkce_verifier.clone().ok_or(Error::Authorize)?;
+
+        let token = self
+            .client
+            .exchange_code(AuthorizationCode::new(code))
+            .set_pkce_verifier(PkceCodeVerifier::new(pkce_verifier))
+            .request_async(async_http_client)
+            .await
+            .map_err(|_| Error::Authorize)?;
+
+        let id_token = token.id_token().ok_or(Error::Authorize)?;
+        let claims = id_token
+            .claims(&self.client.id_token_verifier(), &Nonce::new(nonce))
+            .map_err(|_| Error::Authorize)?;
+
+        // Some providers leave the email out of ID tokens and only serve it from UserInfo.
+        let (email, email_verified) = if let Some(email) = claims.email() {
+            (email.to_string(), claims.email_verified())
+        } else {
+            let user_info: CoreUserInfoClaims = self
+                .client
+                .user_info(token.access_token().clone(), Some(claims.subject().clone()))
+                .map_err(|_| Error::Authorize)?
+                .request_async(async_http_client)
+                .await
+                .map_err(|_| Error::Authorize)?;
+            let email = user_info.email().ok_or(Error::Authorize)?;
+            (email.to_string(), user_info.email_verified())
+        };
+        if email_verified != Some(true) {
+            return Err(Error::Authorize.into());
+        }
+
+        Ok(VerifiedEmails {
+            primary_email: email.clone(),
+            emails: vec![email],
+        })
+    }
+}
@@ -1,11 +1,28 @@
+mod album;
 mod api;
 mod asset;
 mod auth;
+mod share;
+mod trash;
 
-use axum::{http::StatusCode, response, routing, Router};
+use std::{collections::BTreeSet, sync::Arc, time::Duration};
+
+use axum::{
+    http::{header::IF_MATCH, HeaderMap, HeaderValue, StatusCode},
+    response, routing, Router,
+};
 use axum_extra::routing::SpaRouter;
 
-use crate::{config::CONFIG, types::error::Error};
+use crate::{
+    config::CONFIG,
+    index::MetadataIndex,
+    s3,
+    storage::{PutCondition, Storage},
+    types::{
+        asset::{is_valid_name, MetadataWithName},
+        error::Error,
+    },
+};
 
 struct ResponseError(anyhow::Error);
 
@@ -27,7 +44,27 @@ impl response::IntoResponse for ResponseError {
             match error {
                 Error::UserNotAuthorized => StatusCode::UNAUTHORIZED,
                 Error::UserNotAllowed => StatusCode::FORBIDDEN,
+                Error::PermissionDenied => StatusCode::FORBIDDEN,
                 Error::Authorize => StatusCode::INTERNAL_SERVER_ERROR,
+                Error::OAuthStateMismatch => StatusCode::BAD_REQUEST,
+                Error::ProviderNotFound => StatusCode::NOT_FOUND,
+                Error::InvalidName => StatusCode::BAD_REQUEST,
+                Error::PhotoAlreadyExists => StatusCode::CONFLICT,
+                Error::PhotoAlreadyInTrash => StatusCode::CONFLICT,
+                Error::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+                Error::RangeNotSatisfiable => StatusCode::RANGE_NOT_SATISFIABLE,
+                Error::PhotoNotFound => StatusCode::NOT_FOUND,
+                Error::PhotoTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+                Error::InvalidPhotoSize => StatusCode::BAD_REQUEST,
+                Error::UnsupportedContentType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                Error::RevisionNotFound => StatusCode::NOT_FOUND,
+                Error::AlbumNotFound => StatusCode::NOT_FOUND,
+                Error::InvalidAlbum => StatusCode::BAD_REQUEST,
+                Error::ShareNotFound => StatusCode::NOT_FOUND,
+                Error::InvalidSharePassword => StatusCode::UNAUTHORIZED,
+                Error::InvalidShare => StatusCode::BAD_REQUEST,
+                Error::DirectUploadUnavailable => StatusCode::NOT_IMPLEMENTED,
+                Error::InvalidQuery(_) => StatusCode::BAD_REQUEST,
                 Error::S3(_) => StatusCode::INTERNAL_SERVER_ERROR,
             }
         } else {
@@ -39,11 +76,35 @@ impl response::IntoResponse for ResponseError {
 
 type ResponseResult<T> = std::result::Result<T, ResponseError>;
 
+/// Names and IDs from paths end up in object keys, so anything which could escape the prefix is
+/// rejected.
+fn validate_name(name: &str) -> Result<(), Error> {
+    if is_valid_name(name) {
+        Ok(())
+    } else {
+        Err(Error::InvalidName)
+    }
+}
+
+/// Without `If-Match`, the write is made on condition of `e_tag`, which is what the handler read
+/// before modifying it, so that a concurrent write in the meantime is not lost.
+fn put_condition_from_headers(
+    headers: &HeaderMap,
+    e_tag: Option<String>,
+) -> Result<PutCondition, Error> {
+    match headers.get(IF_MATCH).map(HeaderValue::to_str) {
+        Some(Ok(e_tag)) => Ok(PutCondition::IfMatch(e_tag.to_string())),
+        Some(Err(_)) => Err(Error::PreconditionFailed),
+        None => Ok(e_tag.map_or(PutCondition::Always, PutCondition::IfMatch)),
+    }
+}
+
 #[derive(Clone)]
 pub struct AppState {
     http_client: reqwest::Client,
-    oauth_client: oauth2::basic::BasicClient,
-    s3_client: aws_sdk_s3::Client,
+    auth_providers: Arc<Vec<Box<dyn self::auth::Provider>>>,
+    storage: Arc<dyn Storage>,
+    metadata_index: Option<Arc<MetadataIndex>>,
 }
 
 impl AppState {
@@ -56,14 +117,72 @@ impl AppState {
             ))
             .build()
             .unwrap();
-        let oauth_client = self::auth::create_oauth_client();
-        let aws_config = aws_config::load_from_env().await;
-        let s3_client = aws_sdk_s3::Client::new(&aws_config);
+        let auth_providers = Arc::new(self::auth::create_providers().await);
+        let storage = crate::storage::from_config()
+            .await
+            .expect("failed to create storage");
+
+        if CONFIG.check_consistency_on_startup {
+            let storage = storage.clone();
+            tokio::spawn(async move {
+                match s3::check_consistency(&*storage).await {
+                    Ok(report) if report.is_consistent() => {
+                        tracing::info!("storage is consistent");
+                    }
+                    Ok(report) => tracing::warn!(
+                        orphan_photos = ?report.orphan_photos,
+                        orphan_metadatas = ?report.orphan_metadatas,
+                        broken_metadatas = ?report.broken_metadatas,
+                        "storage is inconsistent"
+                    ),
+                    Err(error) => tracing::warn!(%error, "failed to check storage consistency"),
+                }
+            });
+        }
+
+        if CONFIG.trash_retention_secs > 0 {
+            let storage = storage.clone();
+            tokio::spawn(async move {
+                let retention = Duration::from_secs(CONFIG.trash_retention_secs);
+                let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+                loop {
+                    interval.tick().await;
+                    match s3::purge_expired_trash(&*storage, retention).await {
+                        Ok(0) => {}
+                        Ok(count) => tracing::info!(%count, "purged expired trash"),
+                        Err(error) => tracing::error!(%error, "failed to purge expired trash"),
+                    }
+                }
+            });
+        }
+
+        let metadata_index = if CONFIG.metadata_index {
+            let metadata_index = MetadataIndex::build(&*storage)
+                .await
+                .expect("failed to build metadata index");
+            let metadata_index = Arc::new(metadata_index);
+            metadata_index.clone().spawn_refresh(
+                storage.clone(),
+                Duration::from_secs(CONFIG.metadata_index_refresh_interval_secs),
+            );
+            Some(metadata_index)
+        } else {
+            None
+        };
 
         Self {
             http_client,
-            oauth_client,
-            s3_client,
+            auth_providers,
+            storage,
+            metadata_index,
+        }
+    }
+
+    async fn list_metadatas(&self) -> anyhow::Result<BTreeSet<MetadataWithName>> {
+        if let Some(metadata_index) = &self.metadata_index {
+            Ok(metadata_index.metadatas())
+        } else {
+            s3::list_metadatas(&*self.storage).await
         }
     }
 }
@@ -72,11 +191,13 @@ pub async fn create_router() -> Router {
     let api = self::api::create_api_router();
     let asset = self::asset::create_asset_router();
     let auth = self::auth::create_auth_router();
+    let share = self::share::create_public_share_router();
 
     Router::new()
         .nest("/api", api)
         .nest("/asset", asset)
         .nest("/auth", auth)
+        .nest("/share", share)
         .with_state(AppState::new().await)
         .route("/health", routing::get(handle_get_health))
         .merge(SpaRouter::new("/static", &CONFIG.static_file_directory))
@@ -0,0 +1,301 @@
+use std::collections::BTreeMap;
+
+use axum::{
+    extract::{Path, Query, State},
+    headers,
+    response::Response,
+    routing, Json, Router, TypedHeader,
+};
+use chrono::{DateTime, Duration, Utc};
+use http::{header::SET_COOKIE, HeaderMap};
+use jsonwebtoken::{decode, encode, Validation};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    config::{Role, CONFIG},
+    s3,
+    storage::StorageError,
+    types::{
+        album::Album,
+        asset::MetadataWithName,
+        error::Error,
+        share::{Share, ShareCreationRequest, ShareTarget, ShareWithId},
+    },
+};
+
+use super::{asset::photo_response, auth::User, validate_name, AppState, ResponseResult};
+
+/// Management of shares by signed in users, under `/api/share`.
+pub(super) fn create_share_router() -> Router<AppState> {
+    Router::new()
+        .route("/", routing::get(handle_get_shares).post(handle_post_share))
+        .route("/:id", routing::delete(handle_delete_share))
+}
+
+/// Read-only access through a share, without signing in.
+pub(super) fn create_public_share_router() -> Router<AppState> {
+    Router::new()
+        .route("/:id", routing::get(handle_get_shared))
+        .route("/:id/unlock", routing::post(handle_post_shared_unlock))
+        .route("/:id/photo/:name", routing::get(handle_get_shared_photo))
+}
+
+static SHARE_COOKIE_NAME: &str = "SHARE";
+
+/// Proof that the password of a share was given, kept in a cookie so that the password itself is
+/// sent only once rather than with every request.
+#[derive(Deserialize, Serialize)]
+struct UnlockedShare {
+    id: String,
+    exp: i64,
+}
+
+/// Path of the cookie of a share, so that each share has its own.
+fn share_cookie_path(id: &str) -> String {
+    let url = CONFIG.public_url.join(&format!("./share/{}", id)).unwrap();
+    url.path().to_string()
+}
+
+fn is_unlocked(cookies: Option<&headers::Cookie>, id: &str) -> bool {
+    let token = match cookies.and_then(|cookies| cookies.get(SHARE_COOKIE_NAME)) {
+        Some(token) => token,
+        None => return false,
+    };
+    let mut jwt_validation = Validation::default();
+    jwt_validation.validate_exp = true;
+    match decode::<UnlockedShare>(token, &CONFIG.jwt_secret.1, &jwt_validation) {
+        Ok(unlocked) => unlocked.claims.id == id,
+        Err(_) => false,
+    }
+}
+
+fn map_share_error(error: anyhow::Error) -> Error {
+    match error.downcast_ref::<StorageError>() {
+        Some(StorageError::NotFound) => Error::ShareNotFound,
+        _ => Error::S3(error),
+    }
+}
+
+async fn handle_get_shares(
+    user: User,
+    State(state): State<AppState>,
+) -> ResponseResult<Json<Vec<ShareWithId>>> {
+    let mut shares = s3::list_shares(&*state.storage).await.map_err(Error::S3)?;
+    shares.retain(|share| user.require_owner(&share.owner_email).is_ok());
+    shares.sort_by_key(|share| std::cmp::Reverse(share.created_at));
+    Ok(Json(shares))
+}
+
+#[derive(Serialize)]
+struct PostShareResp {
+    id: String,
+}
+
+async fn handle_post_share(
+    user: User,
+    State(state): State<AppState>,
+    Json(req): Json<ShareCreationRequest>,
+) -> ResponseResult<Json<PostShareResp>> {
+    user.require(Role::Uploader)?;
+    let share = req.create(user.primary_email);
+    if share.is_expired() {
+        return Err(Error::InvalidShare.into());
+    }
+    match &share.target {
+        ShareTarget::Photo { name } => {
+            validate_name(name)?;
+            s3::read_metadata(&*state.storage, name)
+                .await
+                .map_err(|_| Error::PhotoNotFound)?;
+        }
+        ShareTarget::Tag { .. } => {}
+        ShareTarget::Album { id } => {
+            validate_name(id).map_err(|_| Error::AlbumNotFound)?;
+            s3::read_album(&*state.storage, id)
+                .await
+                .map_err(|_| Error::AlbumNotFound)?;
+        }
+    }
+
+    // The ID is the only secret of a share without a password, so it must not be guessable.
+    let id = Uuid::new_v4().simple().to_string();
+    s3::upload_share(&*state.storage, &id, &share)
+        .await
+        .map_err(Error::S3)?;
+    Ok(Json(PostShareResp { id }))
+}
+
+async fn handle_delete_share(
+    user: User,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> ResponseResult<()> {
+    validate_name(&id).map_err(|_| Error::ShareNotFound)?;
+    let share = s3::read_share(&*state.storage, &id)
+        .await
+        .map_err(map_share_error)?;
+    user.require_owner(&share.owner_email)?;
+    s3::delete_share(&*state.storage, &id)
+        .await
+        .map_err(Error::S3)?;
+    Ok(())
+}
+
+/// Reads a share which is not expired, without checking its password.
+async fn read_open_share(state: &AppState, id: &str) -> Result<Share, Error> {
+    validate_name(id).map_err(|_| Error::ShareNotFound)?;
+    let share = s3::read_share(&*state.storage, id)
+        .await
+        .map_err(map_share_error)?;
+    if share.is_expired() {
+        return Err(Error::ShareNotFound);
+    }
+    Ok(share)
+}
+
+/// Reads a share which is neither expired nor locked by a password which has not been given to
+/// [`handle_post_shared_unlock`].
+async fn open_share(
+    state: &AppState,
+    id: &str,
+    cookies: Option<&headers::Cookie>,
+) -> Result<Share, Error> {
+    let share = read_open_share(state, id).await?;
+    if share.password.is_some() && !is_unlocked(cookies, id) {
+        return Err(Error::InvalidSharePassword);
+    }
+    Ok(share)
+}
+
+#[derive(Deserialize)]
+struct PostSharedUnlockReq {
+    password: String,
+}
+
+/// Checks the password of a share, and hands out a cookie which unlocks the share for an hour, or
+/// until the share expires if that is sooner.
+async fn handle_post_shared_unlock(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(req): Json<PostSharedUnlockReq>,
+) -> ResponseResult<HeaderMap> {
+    let share = read_open_share(&state, &id).await?;
+    if !share.check_password(Some(&req.password)) {
+        return Err(Error::InvalidSharePassword.into());
+    }
+
+    let now = Utc::now();
+    let expires_at = (now + Duration::hours(1)).min(share.expires_at);
+    let max_age = (expires_at - now).num_seconds();
+    let path = share_cookie_path(&id);
+    let unlocked = UnlockedShare {
+        id,
+        exp: expires_at.timestamp(),
+    };
+    let token = encode(&Default::default(), &unlocked, &CONFIG.jwt_secret.0)
+        .map_err(|_| Error::Authorize)?;
+    let cookie = format!(
+        "{}={}; HttpOnly; SameSite=Lax; Path={}; Max-Age={}",
+        SHARE_COOKIE_NAME, token, path, max_age
+    );
+
+    let mut headers = HeaderMap::new();
+    headers.insert(SET_COOKIE, cookie.parse().unwrap());
+    Ok(headers)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GetSharedResp {
+    target: ShareTarget,
+    expires_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    album: Option<Album>,
+    photos: Vec<MetadataWithName>,
+}
+
+async fn handle_get_shared(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    cookies: Option<TypedHeader<headers::Cookie>>,
+) -> ResponseResult<Json<GetSharedResp>> {
+    let share = open_share(&state, &id, cookies.as_deref()).await?;
+    let metadatas = state.list_metadatas().await.map_err(Error::S3)?;
+
+    let (album, photos) = match &share.target {
+        ShareTarget::Photo { name } => (
+            None,
+            metadatas
+                .into_iter()
+                .filter(|metadata| &metadata.name == name)
+                .collect(),
+        ),
+        ShareTarget::Tag { tag } => (
+            None,
+            metadatas
+                .into_iter()
+                .rev()
+                .filter(|metadata| metadata.metadata.tags.contains(tag))
+                .collect(),
+        ),
+        ShareTarget::Album { id } => {
+            let (album, _) = s3::read_album(&*state.storage, id)
+                .await
+                .map_err(|_| Error::AlbumNotFound)?;
+            let mut metadatas = metadatas
+                .into_iter()
+                .map(|metadata| (metadata.name.clone(), metadata))
+                .collect::<BTreeMap<_, _>>();
+            let photos = album
+                .photos
+                .iter()
+                .filter_map(|name| metadatas.remove(name))
+                .collect();
+            (Some(album), photos)
+        }
+    };
+
+    Ok(Json(GetSharedResp {
+        target: share.target,
+        expires_at: share.expires_at,
+        album,
+        photos,
+    }))
+}
+
+#[derive(Deserialize)]
+struct GetSharedPhotoReq {
+    #[serde(default)]
+    size: Option<u32>,
+}
+
+async fn handle_get_shared_photo(
+    Path((id, name)): Path<(String, String)>,
+    Query(req): Query<GetSharedPhotoReq>,
+    State(state): State<AppState>,
+    cookies: Option<TypedHeader<headers::Cookie>>,
+    headers: HeaderMap,
+) -> ResponseResult<Response> {
+    validate_name(&name)?;
+    let share = open_share(&state, &id, cookies.as_deref()).await?;
+
+    // Photos out of the share are reported missing rather than forbidden, so that a share does not
+    // tell which other photos exist.
+    let is_shared = match &share.target {
+        ShareTarget::Photo { name: shared } => shared == &name,
+        ShareTarget::Tag { tag } => s3::read_metadata(&*state.storage, &name)
+            .await
+            .map(|metadata| metadata.tags.contains(tag))
+            .unwrap_or(false),
+        ShareTarget::Album { id } => s3::read_album(&*state.storage, id)
+            .await
+            .map(|(album, _)| album.photos.contains(&name))
+            .unwrap_or(false),
+    };
+    if !is_shared {
+        return Err(Error::PhotoNotFound.into());
+    }
+
+    photo_response(&state, &name, req.size, &headers).await
+}
@@ -0,0 +1,73 @@
+use axum::{
+    extract::{Path, State},
+    routing, Json, Router,
+};
+
+use crate::{
+    s3,
+    storage::StorageError,
+    types::{asset::TrashedPhoto, error::Error},
+};
+
+use super::{auth::User, validate_name, AppState, ResponseResult};
+
+pub(super) fn create_trash_router() -> Router<AppState> {
+    Router::new()
+        .route("/", routing::get(handle_get_trash))
+        .route("/:name", routing::delete(handle_delete_trash))
+        .route("/:name/restore", routing::post(handle_post_trash_restore))
+}
+
+fn map_trash_error(error: anyhow::Error) -> Error {
+    match error.downcast_ref::<StorageError>() {
+        Some(StorageError::NotFound) => Error::PhotoNotFound,
+        Some(StorageError::PreconditionFailed) => Error::PhotoAlreadyExists,
+        _ => Error::S3(error),
+    }
+}
+
+/// Lists photos in the trash which the user could have deleted, most recently deleted first.
+async fn handle_get_trash(
+    user: User,
+    State(state): State<AppState>,
+) -> ResponseResult<Json<Vec<TrashedPhoto>>> {
+    let mut trash = s3::list_trash(&*state.storage).await.map_err(Error::S3)?;
+    trash.retain(|photo| user.require_modify(&photo.metadata.metadata).is_ok());
+    trash.sort_by_key(|photo| std::cmp::Reverse(photo.deleted_at));
+    Ok(Json(trash))
+}
+
+async fn handle_post_trash_restore(
+    user: User,
+    Path(name): Path<String>,
+    State(state): State<AppState>,
+) -> ResponseResult<()> {
+    validate_name(&name)?;
+    let metadata = s3::read_trashed_metadata(&*state.storage, &name)
+        .await
+        .map_err(map_trash_error)?;
+    user.require_modify(&metadata)?;
+    let metadata = s3::restore_photo(&*state.storage, &name)
+        .await
+        .map_err(map_trash_error)?;
+    if let Some(metadata_index) = &state.metadata_index {
+        metadata_index.insert(name, metadata);
+    }
+    Ok(())
+}
+
+async fn handle_delete_trash(
+    user: User,
+    Path(name): Path<String>,
+    State(state): State<AppState>,
+) -> ResponseResult<()> {
+    validate_name(&name)?;
+    let metadata = s3::read_trashed_metadata(&*state.storage, &name)
+        .await
+        .map_err(map_trash_error)?;
+    user.require_modify(&metadata)?;
+    s3::purge_photo(&*state.storage, &name)
+        .await
+        .map_err(Error::S3)?;
+    Ok(())
+}
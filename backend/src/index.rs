@@ -0,0 +1,133 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+
+use crate::{
+    s3,
+    storage::Storage,
+    types::asset::{Metadata, MetadataWithName},
+};
+
+struct IndexEntry {
+    e_tag: Option<String>,
+    last_modified: Option<DateTime<Utc>>,
+    indexed_at: DateTime<Utc>,
+    metadata: Metadata,
+}
+
+/// In-process cache of every metadata.
+///
+/// It holds nothing which cannot be rebuilt from the storage alone, so the service stays
+/// stateless.
+#[derive(Default)]
+pub struct MetadataIndex {
+    entries: RwLock<BTreeMap<String, IndexEntry>>,
+}
+
+impl MetadataIndex {
+    pub async fn build(storage: &dyn Storage) -> Result<Self> {
+        let index = Self::default();
+        index.refresh(storage).await?;
+        Ok(index)
+    }
+
+    /// Fetches only metadata objects whose ETag or LastModified changed since the last refresh,
+    /// and drops entries whose object disappeared.
+    pub async fn refresh(&self, storage: &dyn Storage) -> Result<()> {
+        let started_at = Utc::now();
+        let objects = s3::list_metadata_objects(storage).await?;
+
+        let changed = {
+            let entries = self.entries.read().unwrap();
+            objects
+                .iter()
+                .filter(|(name, object)| match entries.get(name) {
+                    Some(entry) => {
+                        entry.e_tag != object.e_tag || entry.last_modified != object.last_modified
+                    }
+                    None => true,
+                })
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+
+        let mut fetched = Vec::with_capacity(changed.len());
+        for (name, object) in changed {
+            match s3::read_metadata(storage, &name).await {
+                Ok(metadata) => fetched.push((
+                    name,
+                    IndexEntry {
+                        e_tag: object.e_tag,
+                        last_modified: object.last_modified,
+                        indexed_at: Utc::now(),
+                        metadata,
+                    },
+                )),
+                Err(error) => {
+                    tracing::warn!(%name, %error, "failed to read metadata while refreshing index")
+                }
+            }
+        }
+
+        let names = objects
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<BTreeSet<_>>();
+        let mut entries = self.entries.write().unwrap();
+        // Entries written by handlers while refreshing are newer than what we have listed.
+        entries.retain(|name, entry| names.contains(name) || entry.indexed_at >= started_at);
+        for (name, entry) in fetched {
+            match entries.get(&name) {
+                Some(existing) if existing.indexed_at >= started_at => {}
+                _ => {
+                    entries.insert(name, entry);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn spawn_refresh(self: Arc<Self>, storage: Arc<dyn Storage>, period: Duration) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                if let Err(error) = self.refresh(&*storage).await {
+                    tracing::error!(%error, "failed to refresh metadata index");
+                }
+            }
+        });
+    }
+
+    pub fn insert(&self, name: String, metadata: Metadata) {
+        self.entries.write().unwrap().insert(
+            name,
+            IndexEntry {
+                e_tag: None,
+                last_modified: None,
+                indexed_at: Utc::now(),
+                metadata,
+            },
+        );
+    }
+
+    pub fn remove(&self, name: &str) {
+        self.entries.write().unwrap().remove(name);
+    }
+
+    pub fn metadatas(&self) -> BTreeSet<MetadataWithName> {
+        self.entries
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, entry)| entry.metadata.clone().with_name(name.clone()))
+            .collect()
+    }
+}
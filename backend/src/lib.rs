@@ -0,0 +1,9 @@
+pub mod archive;
+pub mod config;
+pub mod handler;
+pub mod index;
+pub mod photo;
+pub mod s3;
+pub mod search;
+pub mod storage;
+pub mod types;
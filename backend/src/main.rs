@@ -1,8 +1,3 @@
-mod config;
-mod handler;
-mod s3;
-mod types;
-
 use anyhow::Result;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
@@ -47,9 +42,9 @@ async fn main() -> Result<()> {
         )
         .init();
 
-    let router = crate::handler::create_router().await;
+    let router = cheph_backend::handler::create_router().await;
 
-    let listen_addr = &crate::config::CONFIG.listen_addr;
+    let listen_addr = &cheph_backend::config::CONFIG.listen_addr;
     tracing::info!(%listen_addr, "starting http server...");
     axum::Server::bind(&listen_addr.parse()?)
         .serve(router.into_make_service())
@@ -0,0 +1,75 @@
+use std::io::Cursor;
+
+use chrono::{DateTime, FixedOffset, NaiveDate, TimeZone, Utc};
+use exif::{Field, In, Reader, Tag, Value};
+
+use crate::types::asset::Exif;
+
+fn ascii(field: &Field) -> Option<String> {
+    match &field.value {
+        Value::Ascii(values) => values
+            .first()
+            .map(|value| String::from_utf8_lossy(value).trim().to_string())
+            .filter(|value| !value.is_empty()),
+        _ => None,
+    }
+}
+
+fn taken_at(exif: &exif::Exif) -> Option<DateTime<Utc>> {
+    let field = exif.get_field(Tag::DateTimeOriginal, In::PRIMARY)?;
+    let mut datetime = match &field.value {
+        Value::Ascii(values) => exif::DateTime::from_ascii(values.first()?).ok()?,
+        _ => return None,
+    };
+    if let Some(Value::Ascii(values)) = exif
+        .get_field(Tag::OffsetTimeOriginal, In::PRIMARY)
+        .map(|field| &field.value)
+    {
+        if let Some(value) = values.first() {
+            let _ = datetime.parse_offset(value);
+        }
+    }
+
+    let naive = NaiveDate::from_ymd_opt(
+        datetime.year.into(),
+        datetime.month.into(),
+        datetime.day.into(),
+    )?
+    .and_hms_opt(
+        datetime.hour.into(),
+        datetime.minute.into(),
+        datetime.second.into(),
+    )?;
+    // Cameras without an offset record local time, which we can only take as UTC.
+    let offset = FixedOffset::east_opt(i32::from(datetime.offset.unwrap_or(0)) * 60)?;
+    Some(
+        offset
+            .from_local_datetime(&naive)
+            .single()?
+            .with_timezone(&Utc),
+    )
+}
+
+/// Extracts EXIF from any container `kamadak-exif` understands, such as JPEG, HEIF, PNG and WebP.
+pub(super) fn read_exif(bytes: &[u8]) -> Option<Exif> {
+    let exif = Reader::new()
+        .read_from_container(&mut Cursor::new(bytes))
+        .ok()?;
+    let field = |tag| exif.get_field(tag, In::PRIMARY);
+    let uint = |tag| field(tag).and_then(|field: &Field| field.value.get_uint(0));
+    let display = |tag| field(tag).map(|field: &Field| field.display_value().to_string());
+
+    Some(Exif {
+        taken_at: taken_at(&exif),
+        make: field(Tag::Make).and_then(ascii),
+        model: field(Tag::Model).and_then(ascii),
+        lens_model: field(Tag::LensModel).and_then(ascii),
+        exposure_time: display(Tag::ExposureTime),
+        f_number: display(Tag::FNumber),
+        iso: uint(Tag::PhotographicSensitivity),
+        focal_length: display(Tag::FocalLength),
+        orientation: uint(Tag::Orientation).and_then(|value| value.try_into().ok()),
+        width: uint(Tag::PixelXDimension),
+        height: uint(Tag::PixelYDimension),
+    })
+}
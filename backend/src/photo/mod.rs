@@ -0,0 +1,53 @@
+mod exif;
+mod sniff;
+mod thumbnail;
+
+use anyhow::Result;
+
+pub use self::{
+    sniff::{is_supported_content_type, sniff_body, sniff_content_type, SNIFF_LEN},
+    thumbnail::pick_size,
+};
+
+use crate::{
+    config::CONFIG,
+    s3,
+    storage::{GetOptions, Storage},
+    types::asset::Exif,
+};
+
+/// Reads back an uploaded photo once to extract EXIF and to generate every resized variant.
+///
+/// Photos which cannot be decoded as an image (e.g. videos) are left without variants, and the
+/// original is served instead.
+pub async fn process(storage: &dyn Storage, name: &str) -> Result<Option<Exif>> {
+    let object = s3::get_photo(storage, name, GetOptions::default()).await?;
+    if object.content_length > CONFIG.thumbnail_max_source_size {
+        tracing::debug!(%name, "photo is too big to process");
+        return Ok(None);
+    }
+    let bytes = object.into_bytes().await?;
+
+    let (mut exif, thumbnails) = tokio::task::spawn_blocking(move || {
+        let exif = self::exif::read_exif(&bytes);
+        let thumbnails =
+            thumbnail::resize(&bytes, &CONFIG.thumbnail_sizes, CONFIG.thumbnail_format);
+        (exif, thumbnails)
+    })
+    .await?;
+
+    match thumbnails {
+        Ok(thumbnails) => {
+            let exif = exif.get_or_insert_with(Exif::default);
+            exif.width = exif.width.or(Some(thumbnails.width));
+            exif.height = exif.height.or(Some(thumbnails.height));
+
+            for (size, thumbnail) in thumbnails.variants {
+                s3::upload_thumbnail(storage, size, name, thumbnail).await?;
+            }
+        }
+        Err(error) => tracing::debug!(%name, %error, "photo is not a decodable image"),
+    }
+
+    Ok(exif)
+}
@@ -0,0 +1,74 @@
+use anyhow::Result;
+use axum::body::{Body, Bytes, HttpBody};
+use futures_util::StreamExt;
+
+/// Enough bytes to tell every supported format apart.
+pub const SNIFF_LEN: usize = 64;
+
+const SUPPORTED_CONTENT_TYPES: &[&str] = &[
+    "image/jpeg",
+    "image/png",
+    "image/gif",
+    "image/webp",
+    "image/heic",
+    "image/avif",
+    "video/mp4",
+    "video/quicktime",
+];
+
+pub fn is_supported_content_type(content_type: &str) -> bool {
+    SUPPORTED_CONTENT_TYPES.contains(&content_type)
+}
+
+/// HEIC, AVIF, MP4 and MOV all start with an `ftyp` box listing the brands they conform to.
+fn sniff_ftyp(head: &[u8]) -> Option<&'static str> {
+    if head.get(4..8)? != b"ftyp" {
+        return None;
+    }
+    let box_size = u32::from_be_bytes(head.get(0..4)?.try_into().ok()?) as usize;
+    let major_brand = head.get(8..12)?;
+    let compatible_brands = head
+        .get(16..box_size.min(head.len()))
+        .unwrap_or_default()
+        .chunks_exact(4);
+    std::iter::once(major_brand)
+        .chain(compatible_brands)
+        .find_map(|brand| match brand {
+            b"avif" | b"avis" => Some("image/avif"),
+            b"heic" | b"heix" | b"heim" | b"heis" | b"hevc" | b"hevx" => Some("image/heic"),
+            b"qt  " => Some("video/quicktime"),
+            b"isom" | b"iso2" | b"iso4" | b"iso5" | b"iso6" | b"mp41" | b"mp42" | b"avc1"
+            | b"M4V " | b"dash" => Some("video/mp4"),
+            _ => None,
+        })
+}
+
+/// Detects the content type from the first bytes of a file, returning `None` for unsupported
+/// formats.
+pub fn sniff_content_type(head: &[u8]) -> Option<&'static str> {
+    if head.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if head.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if head.starts_with(b"GIF87a") || head.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if head.starts_with(b"RIFF") && head.get(8..12) == Some(b"WEBP") {
+        Some("image/webp")
+    } else {
+        sniff_ftyp(head)
+    }
+}
+
+/// Detects the content type of a body, returning the body as if it were never read.
+pub async fn sniff_body(mut body: Body) -> Result<(Option<&'static str>, Body)> {
+    let mut head = Vec::new();
+    while head.len() < SNIFF_LEN {
+        match body.data().await {
+            Some(chunk) => head.extend_from_slice(&chunk?),
+            None => break,
+        }
+    }
+    let content_type = sniff_content_type(&head);
+    let head = futures_util::stream::once(async move { Ok(Bytes::from(head)) });
+    Ok((content_type, Body::wrap_stream(head.chain(body))))
+}
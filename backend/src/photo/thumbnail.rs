@@ -0,0 +1,71 @@
+use std::io::Cursor;
+
+use anyhow::Result;
+use image::{
+    codecs::{jpeg::JpegEncoder, webp::WebPEncoder},
+    DynamicImage, ImageDecoder, ImageReader,
+};
+
+use crate::config::{ThumbnailFormat, CONFIG};
+
+fn decode(bytes: &[u8]) -> Result<DynamicImage> {
+    let mut decoder = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()?
+        .into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+    Ok(image)
+}
+
+fn encode(image: &DynamicImage, format: ThumbnailFormat) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    match format {
+        ThumbnailFormat::Jpeg => {
+            DynamicImage::ImageRgb8(image.to_rgb8())
+                .write_with_encoder(JpegEncoder::new_with_quality(&mut buf, 85))?;
+        }
+        ThumbnailFormat::Webp => {
+            DynamicImage::ImageRgba8(image.to_rgba8())
+                .write_with_encoder(WebPEncoder::new_lossless(&mut buf))?;
+        }
+    }
+    Ok(buf)
+}
+
+pub(super) struct Thumbnails {
+    pub width: u32,
+    pub height: u32,
+    pub variants: Vec<(u32, Vec<u8>)>,
+}
+
+/// Decodes a photo and encodes it once for every size, never scaling up.
+pub(super) fn resize(bytes: &[u8], sizes: &[u32], format: ThumbnailFormat) -> Result<Thumbnails> {
+    let image = decode(bytes)?;
+    let variants = sizes
+        .iter()
+        .map(|size| {
+            let thumbnail = if image.width().max(image.height()) > *size {
+                image.thumbnail(*size, *size)
+            } else {
+                image.clone()
+            };
+            Ok((*size, encode(&thumbnail, format)?))
+        })
+        .collect::<Result<_>>()?;
+    Ok(Thumbnails {
+        width: image.width(),
+        height: image.height(),
+        variants,
+    })
+}
+
+/// Picks the smallest variant which is at least as big as requested.
+pub fn pick_size(requested: u32) -> Option<u32> {
+    CONFIG
+        .thumbnail_sizes
+        .iter()
+        .copied()
+        .find(|size| *size >= requested)
+        .or_else(|| CONFIG.thumbnail_sizes.last().copied())
+}
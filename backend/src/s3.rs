@@ -1,13 +1,27 @@
-use std::collections::BTreeSet;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    future::Future,
+    sync::Arc,
+    time::Duration,
+};
 
 use anyhow::Result;
-use aws_sdk_s3::{output::GetObjectOutput, Client};
 use axum::body::Body;
+use chrono::Utc;
 use futures_util::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 use crate::{
     config::CONFIG,
-    types::asset::{Metadata, MetadataWithName},
+    photo,
+    storage::{GetOptions, Object, ObjectInfo, PutCondition, PutOptions, Storage, StorageError},
+    types::{
+        album::{Album, AlbumWithId},
+        asset::{Metadata, MetadataWithName, TrashedPhoto},
+        history::HistoryEntry,
+        share::{Share, ShareWithId},
+    },
 };
 
 fn key_photo(name: &str) -> String {
@@ -18,106 +32,1081 @@ fn key_metadata(name: &str) -> String {
     format!("metadata/{}.json", name)
 }
 
-async fn get_object(s3_client: &Client, key: &str) -> Result<GetObjectOutput> {
-    let resp = s3_client
-        .get_object()
-        .bucket(&CONFIG.s3_bucket_name)
-        .key(key)
-        .send()
+/// Where a direct upload is staged until it is committed under a name.
+fn key_upload(id: &str) -> String {
+    format!("upload/{}", id)
+}
+
+fn key_thumbnail(size: u32, name: &str) -> String {
+    format!("thumb/{}/{}", size, name)
+}
+
+fn key_trash_photo(name: &str) -> String {
+    format!("trash/photo/{}", name)
+}
+
+fn key_trash_metadata(name: &str) -> String {
+    format!("trash/metadata/{}.json", name)
+}
+
+fn key_history(name: &str) -> String {
+    format!("history/{}.jsonl", name)
+}
+
+fn key_trash_history(name: &str) -> String {
+    format!("trash/history/{}.jsonl", name)
+}
+
+fn key_album(id: &str) -> String {
+    format!("album/{}.json", id)
+}
+
+fn key_share(id: &str) -> String {
+    format!("share/{}.json", id)
+}
+
+/// Name of the photo a resized variant was made from.
+pub fn name_from_key_thumbnail(key: &str) -> Option<&str> {
+    key.strip_prefix("thumb/")
+        .and_then(|key| key.split_once('/'))
+        .map(|(_, name)| name)
+}
+
+fn name_from_key_metadata(key: &str) -> &str {
+    key.strip_prefix("metadata/")
+        .unwrap_or(key)
+        .strip_suffix(".json")
+        .unwrap_or(key)
+}
+
+static KEY_SNAPSHOT: &str = "index/snapshot.json";
+
+/// Every metadata in a single object, so that listing needs one GetObject instead of one per
+/// photo. Per-photo metadata objects stay the source of truth.
+#[derive(Deserialize, Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct Snapshot {
+    generation: u64,
+    metadatas: Vec<SnapshotEntry>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SnapshotEntry {
+    #[serde(flatten)]
+    metadata: MetadataWithName,
+    /// ETag of the metadata object this entry was read from, to find out stale entries.
+    e_tag: Option<String>,
+}
+
+/// What [`read_snapshot`] reads, with the ETag to write the next generation on condition of.
+struct SnapshotRead {
+    generation: u64,
+    entries: BTreeMap<String, SnapshotEntry>,
+    e_tag: Option<String>,
+}
+
+async fn read_snapshot(storage: &dyn Storage) -> Option<SnapshotRead> {
+    let object = match storage.get_object(KEY_SNAPSHOT).await {
+        Ok(object) => object,
+        Err(error) => {
+            tracing::debug!(%error, "failed to get index snapshot");
+            return None;
+        }
+    };
+    let e_tag = object.e_tag.clone();
+    let body = object.into_bytes().await.ok()?;
+    match serde_json::from_slice::<Snapshot>(&body) {
+        Ok(snapshot) => {
+            let entries = snapshot
+                .metadatas
+                .into_iter()
+                .map(|entry| (entry.metadata.name.clone(), entry))
+                .collect();
+            Some(SnapshotRead {
+                generation: snapshot.generation,
+                entries,
+                e_tag,
+            })
+        }
+        Err(error) => {
+            tracing::warn!(%error, "index snapshot is broken");
+            None
+        }
+    }
+}
+
+async fn write_snapshot(
+    storage: &dyn Storage,
+    generation: u64,
+    entries: BTreeMap<String, SnapshotEntry>,
+    condition: PutCondition,
+) -> Result<()> {
+    let snapshot = Snapshot {
+        generation,
+        metadatas: entries.into_values().collect(),
+    };
+    let options = PutOptions {
+        condition,
+        content_type: Some("application/json".to_string()),
+    };
+    storage
+        .put_object_with(KEY_SNAPSHOT, serde_json::to_vec(&snapshot)?.into(), options)
         .await?;
-    Ok(resp)
+    Ok(())
+}
+
+tokio::task_local! {
+    static DEFER_SNAPSHOT_UPDATES: ();
 }
 
-pub async fn get_photo(s3_client: &Client, name: &str) -> Result<GetObjectOutput> {
-    get_object(s3_client, &key_photo(name)).await
+/// Runs `f` without updating the snapshot on each metadata write, as rewriting the whole snapshot
+/// per write makes writing many metadatas quadratic. The snapshot is left stale, to be repaired by
+/// [`list_metadatas`] or [`rebuild_snapshot`] afterwards.
+pub async fn defer_snapshot_updates<F: Future>(f: F) -> F::Output {
+    DEFER_SNAPSHOT_UPDATES.scope((), f).await
 }
 
-pub async fn get_metadata(s3_client: &Client, name: &str) -> Result<GetObjectOutput> {
-    get_object(s3_client, &key_metadata(name)).await
+/// Applies a change to the snapshot, reading it again if a concurrent writer changed it in the
+/// meantime. A missing snapshot is left to be repaired by [`list_metadatas`], and so is an update
+/// which keeps losing to concurrent writers.
+async fn update_snapshot(storage: &dyn Storage, f: impl Fn(&mut BTreeMap<String, SnapshotEntry>)) {
+    const MAX_ATTEMPTS: usize = 3;
+
+    if DEFER_SNAPSHOT_UPDATES.try_with(|_| ()).is_ok() {
+        return;
+    }
+
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let SnapshotRead {
+            generation,
+            mut entries,
+            e_tag,
+        } = match read_snapshot(storage).await {
+            Some(snapshot) => snapshot,
+            None => return,
+        };
+        f(&mut entries);
+        let condition = e_tag.map_or(PutCondition::Always, PutCondition::IfMatch);
+        match write_snapshot(storage, generation + 1, entries, condition).await {
+            Ok(()) => return,
+            Err(error) => match error.downcast_ref::<StorageError>() {
+                Some(StorageError::PreconditionFailed) if attempt < MAX_ATTEMPTS => continue,
+                _ => {
+                    tracing::warn!(%error, "failed to update index snapshot");
+                    return;
+                }
+            },
+        }
+    }
+}
+
+pub async fn get_photo(storage: &dyn Storage, name: &str, options: GetOptions) -> Result<Object> {
+    storage.get_object_with(&key_photo(name), options).await
 }
 
-pub async fn upload_metadata(s3_client: &Client, name: &str, metadata: &Metadata) -> Result<()> {
-    s3_client
-        .put_object()
-        .bucket(&CONFIG.s3_bucket_name)
-        .key(key_metadata(name))
-        .body(serde_json::to_vec(metadata)?.into())
-        .send()
+pub async fn get_thumbnail(
+    storage: &dyn Storage,
+    size: u32,
+    name: &str,
+    options: GetOptions,
+) -> Result<Object> {
+    storage
+        .get_object_with(&key_thumbnail(size, name), options)
+        .await
+}
+
+pub async fn upload_thumbnail(
+    storage: &dyn Storage,
+    size: u32,
+    name: &str,
+    thumbnail: Vec<u8>,
+) -> Result<()> {
+    let options = PutOptions {
+        condition: PutCondition::Always,
+        content_type: Some(CONFIG.thumbnail_format.content_type().to_string()),
+    };
+    storage
+        .put_object_with(&key_thumbnail(size, name), thumbnail.into(), options)
         .await?;
     Ok(())
 }
 
-pub async fn upload_photo(
-    s3_client: &Client,
+/// Makes a presigned URL of the photo, or of its variant of `size` if there is one.
+pub async fn presign_photo(
+    storage: &dyn Storage,
+    size: Option<u32>,
+    name: &str,
+) -> Result<Option<String>> {
+    let expires_in = Duration::from_secs(CONFIG.presigned_url_expiry_secs);
+    if let Some(size) = size {
+        let key = key_thumbnail(size, name);
+        if storage.head_object(&key).await.is_ok() {
+            return storage.presign_get_object(&key, expires_in).await;
+        }
+    }
+    storage
+        .presign_get_object(&key_photo(name), expires_in)
+        .await
+}
+
+/// Makes a presigned URL to upload a photo to, if the storage supports it. The photo is staged
+/// under `id` rather than its name, so that the URL can neither overwrite a photo nor be replayed
+/// after [`commit_upload`].
+pub async fn presign_upload(
+    storage: &dyn Storage,
+    id: &str,
+    content_type: &str,
+    content_length: i64,
+) -> Result<Option<String>> {
+    let expires_in = Duration::from_secs(CONFIG.presigned_url_expiry_secs);
+    storage
+        .presign_put_object(&key_upload(id), content_type, content_length, expires_in)
+        .await
+}
+
+pub async fn head_upload(storage: &dyn Storage, id: &str) -> Result<ObjectInfo> {
+    storage.head_object(&key_upload(id)).await
+}
+
+pub async fn delete_upload(storage: &dyn Storage, id: &str) -> Result<()> {
+    storage.delete_object(&key_upload(id)).await
+}
+
+/// Moves a staged upload to the photo of `name`. Unless `overwrite` is set, fails with
+/// [`StorageError::PreconditionFailed`] if a photo with the name already exists.
+pub async fn commit_upload(
+    storage: &dyn Storage,
+    id: &str,
+    name: &str,
+    overwrite: bool,
+) -> Result<()> {
+    let condition = if overwrite {
+        PutCondition::Always
+    } else {
+        PutCondition::IfAbsent
+    };
+    storage
+        .copy_object(&key_upload(id), &key_photo(name), condition)
+        .await?;
+    if let Err(error) = delete_upload(storage, id).await {
+        tracing::warn!(%id, %error, "failed to delete committed upload");
+    }
+    Ok(())
+}
+
+/// Detects the type of a stored object from its first bytes. Returns it along with the content
+/// type the object was stored with.
+async fn sniff_object(
+    storage: &dyn Storage,
+    key: &str,
+) -> Result<(Option<&'static str>, Option<String>)> {
+    let options = GetOptions {
+        range: Some(format!("bytes=0-{}", photo::SNIFF_LEN - 1)),
+        ..Default::default()
+    };
+    let object = storage.get_object_with(key, options).await?;
+    let stored_content_type = object.content_type.clone();
+    let head = object.into_bytes().await?;
+    Ok((photo::sniff_content_type(&head), stored_content_type))
+}
+
+pub async fn sniff_photo(
+    storage: &dyn Storage,
+    name: &str,
+) -> Result<(Option<&'static str>, Option<String>)> {
+    sniff_object(storage, &key_photo(name)).await
+}
+
+pub async fn sniff_upload(
+    storage: &dyn Storage,
+    id: &str,
+) -> Result<(Option<&'static str>, Option<String>)> {
+    sniff_object(storage, &key_upload(id)).await
+}
+
+pub async fn head_photo(storage: &dyn Storage, name: &str) -> Result<ObjectInfo> {
+    storage.head_object(&key_photo(name)).await
+}
+
+pub async fn head_metadata(storage: &dyn Storage, name: &str) -> Result<ObjectInfo> {
+    storage.head_object(&key_metadata(name)).await
+}
+
+pub async fn get_metadata(
+    storage: &dyn Storage,
+    name: &str,
+    options: GetOptions,
+) -> Result<Object> {
+    storage.get_object_with(&key_metadata(name), options).await
+}
+
+pub async fn upload_metadata(
+    storage: &dyn Storage,
     name: &str,
     metadata: &Metadata,
-    photo_body: Body,
+    condition: PutCondition,
 ) -> Result<()> {
-    s3_client
-        .put_object()
-        .bucket(&CONFIG.s3_bucket_name)
-        .key(key_photo(name))
-        .body(photo_body.into())
-        .send()
+    let options = PutOptions {
+        condition,
+        content_type: Some("application/json".to_string()),
+    };
+    let e_tag = storage
+        .put_object_with(
+            &key_metadata(name),
+            serde_json::to_vec(metadata)?.into(),
+            options,
+        )
         .await?;
 
-    upload_metadata(s3_client, name, metadata).await?;
+    update_snapshot(storage, |entries| {
+        entries.insert(
+            name.to_string(),
+            SnapshotEntry {
+                metadata: metadata.clone().with_name(name.to_string()),
+                e_tag: e_tag.clone(),
+            },
+        );
+    })
+    .await;
 
     Ok(())
 }
 
-pub async fn delete_photo(s3_client: &Client, name: &str) -> Result<()> {
-    s3_client
-        .delete_object()
-        .bucket(&CONFIG.s3_bucket_name)
-        .key(key_photo(name))
-        .send()
+/// Processes an already stored photo of `content_type` and writes its metadata. If the metadata
+/// cannot be written, the photo is deleted, as it would never show up without one.
+pub async fn finish_upload(
+    storage: &dyn Storage,
+    name: &str,
+    metadata: Metadata,
+    content_type: &str,
+) -> Result<Metadata> {
+    match process_and_upload_metadata(storage, name, metadata, content_type).await {
+        Ok(metadata) => {
+            // A new photo starts a new history, even if it replaces one with the same name.
+            let entry = HistoryEntry::between(metadata.creator_email.clone(), None, &metadata);
+            if let Err(error) = write_history(storage, name, &[entry], PutCondition::Always).await {
+                tracing::warn!(%name, %error, "failed to start history");
+            }
+            Ok(metadata)
+        }
+        Err(error) => {
+            if let Err(error) = delete_photo_objects(storage, name).await {
+                tracing::error!(%name, %error, "failed to delete photo without metadata");
+            }
+            Err(error)
+        }
+    }
+}
+
+async fn process_and_upload_metadata(
+    storage: &dyn Storage,
+    name: &str,
+    mut metadata: Metadata,
+    content_type: &str,
+) -> Result<Metadata> {
+    metadata.content_type = Some(content_type.to_string());
+    metadata.size = Some(head_photo(storage, name).await?.size);
+
+    match photo::process(storage, name).await {
+        Ok(exif) => metadata.exif = exif,
+        Err(error) => tracing::warn!(%name, %error, "failed to process photo"),
+    }
+
+    upload_metadata(storage, name, &metadata, PutCondition::Always).await?;
+
+    Ok(metadata)
+}
+
+/// Stores a photo with its metadata. Unless `overwrite` is set, fails with
+/// [`StorageError::PreconditionFailed`](crate::storage::StorageError::PreconditionFailed) if a photo with the name already exists.
+pub async fn upload_photo(
+    storage: &dyn Storage,
+    name: &str,
+    mut metadata: Metadata,
+    photo_body: Body,
+    content_type: &str,
+    overwrite: bool,
+) -> Result<Metadata> {
+    let options = PutOptions {
+        condition: if overwrite {
+            PutCondition::Always
+        } else {
+            PutCondition::IfAbsent
+        },
+        content_type: Some(content_type.to_string()),
+    };
+    let hasher = Arc::new(std::sync::Mutex::new(Sha256::new()));
+    let photo_body = Body::wrap_stream(photo_body.inspect_ok({
+        let hasher = hasher.clone();
+        move |chunk| hasher.lock().unwrap().update(chunk)
+    }));
+    storage
+        .put_object_with(&key_photo(name), photo_body, options)
         .await?;
+    metadata.sha256 = Some(format!("{:x}", hasher.lock().unwrap().clone().finalize()));
+
+    finish_upload(storage, name, metadata, content_type).await
+}
+
+/// Deletes a photo and its resized variants, leaving the metadata alone. Variants which fail to
+/// be deleted are only logged, as nothing refers to them once the photo is gone.
+pub async fn delete_photo_objects(storage: &dyn Storage, name: &str) -> Result<()> {
+    storage.delete_object(&key_photo(name)).await?;
+
+    for size in &CONFIG.thumbnail_sizes {
+        if let Err(error) = storage.delete_object(&key_thumbnail(*size, name)).await {
+            tracing::warn!(%name, %size, %error, "failed to delete resized photo");
+        }
+    }
 
-    s3_client
-        .delete_object()
-        .bucket(&CONFIG.s3_bucket_name)
-        .key(key_metadata(name))
-        .send()
+    Ok(())
+}
+
+/// Deletes a photo with its metadata. The metadata goes first so that the photo disappears from
+/// listings at once, and is put back if the photo cannot be deleted.
+pub async fn delete_photo(storage: &dyn Storage, name: &str) -> Result<()> {
+    let metadata = read_metadata(storage, name).await.ok();
+
+    storage.delete_object(&key_metadata(name)).await?;
+
+    update_snapshot(storage, |entries| {
+        entries.remove(name);
+    })
+    .await;
+
+    if let Err(error) = delete_photo_objects(storage, name).await {
+        if let Some(metadata) = metadata {
+            if let Err(error) =
+                upload_metadata(storage, name, &metadata, PutCondition::Always).await
+            {
+                tracing::error!(%name, %error, "failed to restore metadata of undeleted photo");
+            }
+        }
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+/// Moves a photo with its metadata into the trash, where it stays out of listings until it is
+/// restored or purged. Resized variants are deleted, and generated again on restore, while album
+/// memberships are kept until the photo is purged.
+///
+/// Fails with [`StorageError::PreconditionFailed`] if a photo with the same name is already in the
+/// trash, which would be lost otherwise.
+pub async fn trash_photo(storage: &dyn Storage, name: &str) -> Result<()> {
+    let metadata = read_metadata(storage, name).await?;
+
+    // The metadata goes first to claim the name in the trash.
+    let options = PutOptions {
+        condition: PutCondition::IfAbsent,
+        content_type: Some("application/json".to_string()),
+    };
+    storage
+        .put_object_with(
+            &key_trash_metadata(name),
+            serde_json::to_vec(&metadata)?.into(),
+            options,
+        )
         .await?;
+    if let Err(error) = storage
+        .copy_object(
+            &key_photo(name),
+            &key_trash_photo(name),
+            PutCondition::Always,
+        )
+        .await
+    {
+        delete_trashed_copies(storage, name).await;
+        return Err(error);
+    }
+
+    if let Err(error) = delete_photo(storage, name).await {
+        delete_trashed_copies(storage, name).await;
+        return Err(error);
+    }
+
+    // Out of the way of a photo uploaded with the same name in the meantime.
+    if let Err(error) = move_history(storage, &key_history(name), &key_trash_history(name)).await {
+        tracing::warn!(%name, %error, "failed to move history into the trash");
+    }
 
     Ok(())
 }
 
-pub async fn list_metadatas(s3_client: &Client) -> Result<BTreeSet<MetadataWithName>> {
-    s3_client
-        .list_objects_v2()
-        .bucket(&CONFIG.s3_bucket_name)
-        .prefix("metadata/")
-        .into_paginator()
-        .send()
-        .err_into::<anyhow::Error>()
-        .map_ok(|output| {
-            futures_util::stream::iter(
-                output
-                    .contents
-                    .unwrap_or_default()
-                    .into_iter()
-                    .map(Result::<_, anyhow::Error>::Ok),
-            )
-        })
-        .try_flatten()
-        .try_filter_map(|object| async move {
-            if let Some(key) = object.key() {
-                let resp = get_object(s3_client, key).await?;
-                let body = resp.body.collect().await?.into_bytes();
-                let metadata = serde_json::from_slice::<Metadata>(&body).ok();
-                let name = key
-                    .strip_prefix("metadata/")
-                    .unwrap_or(key)
-                    .strip_suffix(".json")
-                    .unwrap_or(key)
-                    .to_string();
-                Ok(metadata.map(|metadata| metadata.with_name(name)))
-            } else {
-                Ok(None)
+/// Takes back the copies of a photo which could not be moved into the trash, so that it can be
+/// trashed again. The metadata goes last as it claims the name in the trash.
+async fn delete_trashed_copies(storage: &dyn Storage, name: &str) {
+    for key in [key_trash_photo(name), key_trash_metadata(name)] {
+        if let Err(error) = storage.delete_object(&key).await {
+            tracing::error!(%name, %key, %error, "failed to delete trashed copy of undeleted photo");
+        }
+    }
+}
+
+/// Moves a history object, if there is one.
+async fn move_history(storage: &dyn Storage, from: &str, to: &str) -> Result<()> {
+    match storage.copy_object(from, to, PutCondition::Always).await {
+        Ok(()) => storage.delete_object(from).await,
+        Err(error) if matches!(error.downcast_ref(), Some(StorageError::NotFound)) => Ok(()),
+        Err(error) => Err(error),
+    }
+}
+
+pub async fn read_trashed_metadata(storage: &dyn Storage, name: &str) -> Result<Metadata> {
+    let body = storage
+        .get_object(&key_trash_metadata(name))
+        .await?
+        .into_bytes()
+        .await?;
+    Ok(serde_json::from_slice(&body)?)
+}
+
+/// Lists the trash. The deletion time is when the metadata was moved into the trash.
+pub async fn list_trash(storage: &dyn Storage) -> Result<Vec<TrashedPhoto>> {
+    let mut trash = Vec::new();
+    for object in storage.list_objects("trash/metadata/").await? {
+        let name = match object
+            .key
+            .strip_prefix("trash/metadata/")
+            .and_then(|key| key.strip_suffix(".json"))
+        {
+            Some(name) => name,
+            None => continue,
+        };
+        match read_trashed_metadata(storage, name).await {
+            Ok(metadata) => trash.push(TrashedPhoto {
+                metadata: metadata.with_name(name.to_string()),
+                deleted_at: object.last_modified.unwrap_or_else(Utc::now),
+            }),
+            Err(error) => tracing::warn!(%name, %error, "failed to read trashed metadata"),
+        }
+    }
+    Ok(trash)
+}
+
+/// Moves a photo back from the trash. Fails with
+/// [`StorageError::PreconditionFailed`] if a photo with the same name was uploaded since.
+pub async fn restore_photo(storage: &dyn Storage, name: &str) -> Result<Metadata> {
+    let metadata = read_trashed_metadata(storage, name).await?;
+    match storage.head_object(&key_metadata(name)).await {
+        Ok(_) => return Err(StorageError::PreconditionFailed.into()),
+        Err(error) if matches!(error.downcast_ref(), Some(StorageError::NotFound)) => {}
+        Err(error) => return Err(error),
+    }
+
+    // Neither the photo nor the metadata may replace one uploaded in the meantime, and the photo is
+    // only deleted again below because it was written here.
+    storage
+        .copy_object(
+            &key_trash_photo(name),
+            &key_photo(name),
+            PutCondition::IfAbsent,
+        )
+        .await?;
+    if let Err(error) = upload_metadata(storage, name, &metadata, PutCondition::IfAbsent).await {
+        if let Err(error) = storage.delete_object(&key_photo(name)).await {
+            tracing::error!(%name, %error, "failed to delete photo of unrestored metadata");
+        }
+        return Err(error);
+    }
+
+    if let Err(error) = photo::process(storage, name).await {
+        tracing::warn!(%name, %error, "failed to process restored photo");
+    }
+    if let Err(error) = move_history(storage, &key_trash_history(name), &key_history(name)).await {
+        tracing::warn!(%name, %error, "failed to restore history");
+    }
+    if let Err(error) = delete_trashed_objects(storage, name).await {
+        tracing::warn!(%name, %error, "failed to remove restored photo from trash");
+    }
+
+    Ok(metadata)
+}
+
+async fn delete_trashed_objects(storage: &dyn Storage, name: &str) -> Result<()> {
+    storage.delete_object(&key_trash_metadata(name)).await?;
+    storage.delete_object(&key_trash_photo(name)).await?;
+    storage.delete_object(&key_trash_history(name)).await
+}
+
+/// Deletes a photo in the trash for good, and removes it from albums unless a photo with the same
+/// name has been uploaded since.
+pub async fn purge_photo(storage: &dyn Storage, name: &str) -> Result<()> {
+    delete_trashed_objects(storage, name).await?;
+
+    match head_metadata(storage, name).await {
+        Ok(_) => {}
+        Err(error) if matches!(error.downcast_ref(), Some(StorageError::NotFound)) => {
+            if let Err(error) = remove_photo_from_albums(storage, name).await {
+                tracing::warn!(%name, %error, "failed to remove purged photo from albums");
             }
-        })
-        .try_collect()
+        }
+        Err(error) => {
+            tracing::warn!(%name, %error, "failed to check for a photo to keep in albums")
+        }
+    }
+
+    Ok(())
+}
+
+/// Purges every photo which has been in the trash for longer than `retention`. Returns the number
+/// of purged photos.
+pub async fn purge_expired_trash(storage: &dyn Storage, retention: Duration) -> Result<usize> {
+    let retention = chrono::Duration::from_std(retention)?;
+    let mut count = 0;
+    for object in storage.list_objects("trash/metadata/").await? {
+        let name = match object
+            .key
+            .strip_prefix("trash/metadata/")
+            .and_then(|key| key.strip_suffix(".json"))
+        {
+            Some(name) => name,
+            None => continue,
+        };
+        if matches!(object.last_modified, Some(deleted_at) if Utc::now() - deleted_at < retention) {
+            continue;
+        }
+        // One entry which cannot be purged must not keep the rest in the trash.
+        match purge_photo(storage, name).await {
+            Ok(()) => count += 1,
+            Err(error) => tracing::warn!(%name, %error, "failed to purge expired photo"),
+        }
+    }
+    Ok(count)
+}
+
+/// Deletes only the metadata of a photo, e.g. one whose photo is missing.
+pub async fn delete_metadata(storage: &dyn Storage, name: &str) -> Result<()> {
+    storage.delete_object(&key_metadata(name)).await?;
+
+    update_snapshot(storage, |entries| {
+        entries.remove(name);
+    })
+    .await;
+
+    Ok(())
+}
+
+/// Inconsistencies left behind by interrupted uploads and deletions.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConsistencyReport {
+    /// Photos without metadata, which never show up.
+    pub orphan_photos: Vec<String>,
+    /// Metadatas whose photo is missing.
+    pub orphan_metadatas: Vec<String>,
+    /// Metadatas which cannot be parsed.
+    pub broken_metadatas: Vec<String>,
+    /// Keys of resized photos whose photo is missing.
+    pub orphan_thumbnails: Vec<String>,
+    /// IDs of direct uploads which are not committed. They are expected while uploads are in
+    /// progress, so they do not make the storage inconsistent.
+    pub uncommitted_uploads: Vec<String>,
+}
+
+impl ConsistencyReport {
+    pub fn is_consistent(&self) -> bool {
+        self.orphan_photos.is_empty()
+            && self.orphan_metadatas.is_empty()
+            && self.broken_metadatas.is_empty()
+            && self.orphan_thumbnails.is_empty()
+    }
+}
+
+/// Compares every photo against every metadata. Reads every metadata object, so this is slow on
+/// big buckets.
+pub async fn check_consistency(storage: &dyn Storage) -> Result<ConsistencyReport> {
+    let mut photos = storage
+        .list_objects("photo/")
+        .await?
+        .into_iter()
+        .filter_map(|object| object.key.strip_prefix("photo/").map(str::to_string))
+        .collect::<BTreeSet<_>>();
+
+    let mut report = ConsistencyReport::default();
+    for object in storage.list_objects("thumb/").await? {
+        let name = name_from_key_thumbnail(&object.key);
+        if !matches!(name, Some(name) if photos.contains(name)) {
+            report.orphan_thumbnails.push(object.key);
+        }
+    }
+    for (name, object) in list_metadata_objects(storage).await? {
+        let body = storage.get_object(&object.key).await?.into_bytes().await?;
+        if serde_json::from_slice::<Metadata>(&body).is_err() {
+            report.broken_metadatas.push(name.clone());
+        }
+        if !photos.remove(&name) {
+            report.orphan_metadatas.push(name);
+        }
+    }
+    report.orphan_photos = photos.into_iter().collect();
+    report.uncommitted_uploads = storage
+        .list_objects("upload/")
+        .await?
+        .into_iter()
+        .filter_map(|object| object.key.strip_prefix("upload/").map(str::to_string))
+        .collect();
+
+    Ok(report)
+}
+
+/// Lists names of every photo which has a metadata object, with the object information.
+pub async fn list_metadata_objects(storage: &dyn Storage) -> Result<Vec<(String, ObjectInfo)>> {
+    let objects = storage.list_objects("metadata/").await?;
+    Ok(objects
+        .into_iter()
+        .map(|object| (name_from_key_metadata(&object.key).to_string(), object))
+        .collect())
+}
+
+pub async fn read_metadata(storage: &dyn Storage, name: &str) -> Result<Metadata> {
+    let (metadata, _) = read_metadata_with_e_tag(storage, name).await?;
+    Ok(metadata)
+}
+
+/// Reads a metadata with the ETag of its object, to be given back to [`upload_metadata`] as a
+/// condition.
+pub async fn read_metadata_with_e_tag(
+    storage: &dyn Storage,
+    name: &str,
+) -> Result<(Metadata, Option<String>)> {
+    let object = get_metadata(storage, name, GetOptions::default()).await?;
+    let e_tag = object.e_tag.clone();
+    let metadata = serde_json::from_slice(&object.into_bytes().await?)?;
+    Ok((metadata, e_tag))
+}
+
+/// Rebuilds the index snapshot from every metadata object, ignoring what the snapshot has. Returns
+/// the number of metadatas in the new snapshot.
+pub async fn rebuild_snapshot(storage: &dyn Storage) -> Result<usize> {
+    let generation = read_snapshot(storage)
         .await
+        .map_or(0, |snapshot| snapshot.generation);
+
+    let mut entries = BTreeMap::new();
+    for (name, object) in list_metadata_objects(storage).await? {
+        let body = storage.get_object(&object.key).await?.into_bytes().await?;
+        match serde_json::from_slice::<Metadata>(&body) {
+            Ok(metadata) => {
+                entries.insert(
+                    name.clone(),
+                    SnapshotEntry {
+                        metadata: metadata.with_name(name),
+                        e_tag: object.e_tag,
+                    },
+                );
+            }
+            Err(error) => tracing::warn!(%name, %error, "skipping broken metadata"),
+        }
+    }
+
+    let count = entries.len();
+    write_snapshot(storage, generation + 1, entries, PutCondition::Always).await?;
+    Ok(count)
+}
+
+/// Runs [`photo::process`] again for an existing photo, and fills in what its metadata is
+/// missing.
+pub async fn reprocess_photo(storage: &dyn Storage, name: &str) -> Result<Metadata> {
+    let mut metadata = read_metadata(storage, name).await?;
+
+    if metadata.content_type.is_none() {
+        let (content_type, _) = sniff_photo(storage, name).await?;
+        metadata.content_type = content_type.map(str::to_string);
+    }
+    metadata.size = Some(head_photo(storage, name).await?.size);
+    if metadata.sha256.is_none() {
+        let mut hasher = Sha256::new();
+        let mut body = get_photo(storage, name, GetOptions::default()).await?.body;
+        while let Some(chunk) = body.try_next().await? {
+            hasher.update(&chunk);
+        }
+        metadata.sha256 = Some(format!("{:x}", hasher.finalize()));
+    }
+    metadata.exif = photo::process(storage, name).await?;
+
+    upload_metadata(storage, name, &metadata, PutCondition::Always).await?;
+
+    Ok(metadata)
+}
+
+/// Reads every metadata from the snapshot, fetching only metadata objects which are missing in or
+/// changed since the snapshot. The snapshot is rewritten if it had to be repaired.
+pub async fn list_metadatas(storage: &dyn Storage) -> Result<BTreeSet<MetadataWithName>> {
+    let objects = list_metadata_objects(storage).await?;
+    let (generation, mut cached, e_tag, mut stale) = match read_snapshot(storage).await {
+        Some(snapshot) => (snapshot.generation, snapshot.entries, snapshot.e_tag, false),
+        None => (0, BTreeMap::new(), None, true),
+    };
+
+    let mut entries = BTreeMap::new();
+    for (name, object) in objects {
+        match cached.remove(&name) {
+            Some(entry) if entry.e_tag.is_some() && entry.e_tag == object.e_tag => {
+                entries.insert(name, entry);
+                continue;
+            }
+            Some(_) => stale = true,
+            None => {}
+        }
+
+        let body = storage.get_object(&object.key).await?.into_bytes().await?;
+        if let Ok(metadata) = serde_json::from_slice::<Metadata>(&body) {
+            stale = true;
+            entries.insert(
+                name.clone(),
+                SnapshotEntry {
+                    metadata: metadata.with_name(name),
+                    e_tag: object.e_tag,
+                },
+            );
+        }
+    }
+    // Whatever remains belongs to deleted photos.
+    stale |= !cached.is_empty();
+
+    let metadatas = entries
+        .values()
+        .map(|entry| entry.metadata.clone())
+        .collect();
+
+    if stale {
+        tracing::info!(generation = generation + 1, "repairing index snapshot");
+        // A snapshot changed in the meantime is newer than what was read here, so it is kept.
+        let condition = e_tag.map_or(PutCondition::Always, PutCondition::IfMatch);
+        if let Err(error) = write_snapshot(storage, generation + 1, entries, condition).await {
+            tracing::warn!(%error, "failed to repair index snapshot");
+        }
+    }
+
+    Ok(metadatas)
+}
+
+/// Reads an album with the ETag of its object, to be given back to [`upload_album`] as a condition.
+pub async fn read_album(storage: &dyn Storage, id: &str) -> Result<(Album, Option<String>)> {
+    let object = storage.get_object(&key_album(id)).await?;
+    let e_tag = object.e_tag.clone();
+    let album = serde_json::from_slice(&object.into_bytes().await?)?;
+    Ok((album, e_tag))
+}
+
+pub async fn upload_album(
+    storage: &dyn Storage,
+    id: &str,
+    album: &Album,
+    condition: PutCondition,
+) -> Result<()> {
+    let options = PutOptions {
+        condition,
+        content_type: Some("application/json".to_string()),
+    };
+    storage
+        .put_object_with(&key_album(id), serde_json::to_vec(album)?.into(), options)
+        .await?;
+    Ok(())
+}
+
+pub async fn delete_album(storage: &dyn Storage, id: &str) -> Result<()> {
+    storage.delete_object(&key_album(id)).await
+}
+
+/// Reads every album. Albums which cannot be read are skipped with a warning, so that one broken
+/// object does not hide the others.
+pub async fn list_albums(storage: &dyn Storage) -> Result<Vec<AlbumWithId>> {
+    let mut albums = Vec::new();
+    for object in storage.list_objects("album/").await? {
+        let id = match object
+            .key
+            .strip_prefix("album/")
+            .and_then(|key| key.strip_suffix(".json"))
+        {
+            Some(id) => id,
+            None => continue,
+        };
+        match read_album(storage, id).await {
+            Ok((album, _)) => albums.push(album.with_id(id.to_string())),
+            Err(error) => tracing::warn!(%id, %error, "failed to read album"),
+        }
+    }
+    Ok(albums)
+}
+
+/// Removes a photo from every album it is in. Each album is rewritten only if it did not change
+/// since it was read, and read again otherwise.
+pub async fn remove_photo_from_albums(storage: &dyn Storage, name: &str) -> Result<()> {
+    const MAX_ATTEMPTS: usize = 3;
+
+    for AlbumWithId { album, id } in list_albums(storage).await? {
+        if !album.photos.iter().any(|photo| photo == name) {
+            continue;
+        }
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let (mut album, e_tag) = read_album(storage, &id).await?;
+            if !album.remove_photo(name) {
+                break;
+            }
+            let condition = e_tag.map_or(PutCondition::Always, PutCondition::IfMatch);
+            match upload_album(storage, &id, &album, condition).await {
+                Ok(()) => break,
+                Err(error) => match error.downcast_ref::<StorageError>() {
+                    Some(StorageError::PreconditionFailed) if attempt < MAX_ATTEMPTS => continue,
+                    _ => return Err(error),
+                },
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn read_share(storage: &dyn Storage, id: &str) -> Result<Share> {
+    let body = storage
+        .get_object(&key_share(id))
+        .await?
+        .into_bytes()
+        .await?;
+    Ok(serde_json::from_slice(&body)?)
+}
+
+pub async fn upload_share(storage: &dyn Storage, id: &str, share: &Share) -> Result<()> {
+    let options = PutOptions {
+        condition: PutCondition::IfAbsent,
+        content_type: Some("application/json".to_string()),
+    };
+    storage
+        .put_object_with(&key_share(id), serde_json::to_vec(share)?.into(), options)
+        .await?;
+    Ok(())
+}
+
+/// Revokes a share.
+pub async fn delete_share(storage: &dyn Storage, id: &str) -> Result<()> {
+    storage.delete_object(&key_share(id)).await
+}
+
+/// Reads every share, skipping ones which cannot be read as [`list_albums`] does.
+pub async fn list_shares(storage: &dyn Storage) -> Result<Vec<ShareWithId>> {
+    let mut shares = Vec::new();
+    for object in storage.list_objects("share/").await? {
+        let id = match object
+            .key
+            .strip_prefix("share/")
+            .and_then(|key| key.strip_suffix(".json"))
+        {
+            Some(id) => id,
+            None => continue,
+        };
+        match read_share(storage, id).await {
+            Ok(share) => shares.push(share.with_id(id.to_string())),
+            Err(error) => tracing::warn!(%id, %error, "failed to read share"),
+        }
+    }
+    Ok(shares)
+}
+
+/// Reads the history of a photo, oldest first, with the ETag of its object. Photos uploaded before
+/// history was kept start with their first edit.
+pub async fn read_history(
+    storage: &dyn Storage,
+    name: &str,
+) -> Result<(Vec<HistoryEntry>, Option<String>)> {
+    let object = match storage.get_object(&key_history(name)).await {
+        Ok(object) => object,
+        Err(error) if matches!(error.downcast_ref(), Some(StorageError::NotFound)) => {
+            return Ok((Vec::new(), None))
+        }
+        Err(error) => return Err(error),
+    };
+    let e_tag = object.e_tag.clone();
+    let body = object.into_bytes().await?;
+    let entries = body
+        .split(|byte| *byte == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(serde_json::from_slice)
+        .collect::<Result<_, _>>()?;
+    Ok((entries, e_tag))
+}
+
+async fn write_history(
+    storage: &dyn Storage,
+    name: &str,
+    entries: &[HistoryEntry],
+    condition: PutCondition,
+) -> Result<()> {
+    let mut body = Vec::new();
+    for entry in entries {
+        serde_json::to_writer(&mut body, entry)?;
+        body.push(b'\n');
+    }
+    let options = PutOptions {
+        condition,
+        content_type: Some("application/x-ndjson".to_string()),
+    };
+    storage
+        .put_object_with(&key_history(name), body.into(), options)
+        .await?;
+    Ok(())
+}
+
+/// Appends an entry to the history of a photo, reading the history again if another write got in
+/// between.
+pub async fn append_history(storage: &dyn Storage, name: &str, entry: HistoryEntry) -> Result<()> {
+    const MAX_ATTEMPTS: usize = 3;
+
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let (mut entries, e_tag) = read_history(storage, name).await?;
+        let condition = e_tag.map_or(PutCondition::IfAbsent, PutCondition::IfMatch);
+        entries.push(entry.clone());
+        match write_history(storage, name, &entries, condition).await {
+            Ok(()) => return Ok(()),
+            Err(error) => match error.downcast_ref::<StorageError>() {
+                Some(StorageError::PreconditionFailed) if attempt < MAX_ATTEMPTS => continue,
+                _ => return Err(error),
+            },
+        }
+    }
+}
+
+/// Brings the tags and description of a photo back to how they were right after `revision`, by
+/// undoing every later entry, and records that as a new entry. Returns `None` if there is no such
+/// revision.
+pub async fn revert_metadata(
+    storage: &dyn Storage,
+    name: &str,
+    revision: usize,
+    editor_email: String,
+) -> Result<Option<Metadata>> {
+    let object = get_metadata(storage, name, GetOptions::default()).await?;
+    let e_tag = object.e_tag.clone();
+    let current = serde_json::from_slice::<Metadata>(&object.into_bytes().await?)?;
+
+    let (entries, _) = read_history(storage, name).await?;
+    let later = match revision
+        .checked_add(1)
+        .and_then(|start| entries.get(start..))
+    {
+        Some(later) => later,
+        None => return Ok(None),
+    };
+    let mut reverted = current.clone();
+    for entry in later.iter().rev() {
+        entry.undo(&mut reverted);
+    }
+
+    let entry = HistoryEntry::between(editor_email, Some(&current), &reverted);
+    if entry.is_empty() {
+        return Ok(Some(current));
+    }
+    let condition = e_tag.map_or(PutCondition::Always, PutCondition::IfMatch);
+    upload_metadata(storage, name, &reverted, condition).await?;
+    append_history(storage, name, entry).await?;
+
+    Ok(Some(reverted))
 }
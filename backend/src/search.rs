@@ -0,0 +1,520 @@
+//! Query language of the search, e.g. `tag:cat -tag:dog after:2022-01-01 (by:alice OR "red car")`.
+//!
+//! Terms next to each other must all match, and `AND` may be written out between them. `OR` binds
+//! looser than that, and `-` or `NOT` negates the following term or group. Bare words and quoted
+//! phrases match the name, the description or a tag, ignoring case.
+
+use std::{
+    iter::Peekable,
+    str::{Chars, FromStr},
+};
+
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+
+use crate::types::asset::MetadataWithName;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum QueryError {
+    #[error("unterminated quote")]
+    UnterminatedQuote,
+    #[error("unbalanced parenthesis")]
+    UnbalancedParenthesis,
+    #[error("expected a term")]
+    ExpectedTerm,
+    #[error("unknown field `{0}`")]
+    UnknownField(String),
+    #[error("invalid date `{0}`")]
+    InvalidDate(String),
+    #[error("query is nested too deeply")]
+    TooDeep,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    Not,
+    And,
+    Or,
+    /// Bare word or quoted phrase, with the field it is prefixed by.
+    Term {
+        field: Option<String>,
+        value: String,
+    },
+}
+
+/// Reads up to the closing quote, after the opening one.
+fn read_quoted(chars: &mut Peekable<Chars>) -> Result<String, QueryError> {
+    let mut value = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(value),
+            Some(c) => value.push(c),
+            None => return Err(QueryError::UnterminatedQuote),
+        }
+    }
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            _ if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            // Only at the start of a token, so that dates and names keep their dashes.
+            '-' => {
+                chars.next();
+                tokens.push(Token::Not);
+            }
+            '"' => {
+                chars.next();
+                let value = read_quoted(&mut chars)?;
+                tokens.push(Token::Term { field: None, value });
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '(' | ')' | '"') {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                let token = match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => match word.split_once(':') {
+                        Some((field, value)) => {
+                            let value = if value.is_empty() && chars.peek() == Some(&'"') {
+                                chars.next();
+                                read_quoted(&mut chars)?
+                            } else {
+                                value.to_string()
+                            };
+                            Token::Term {
+                                field: Some(field.to_lowercase()),
+                                value,
+                            }
+                        }
+                        None => Token::Term {
+                            field: None,
+                            value: word,
+                        },
+                    },
+                };
+                tokens.push(token);
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Midnight in UTC of a `YYYY-MM-DD` date, or an RFC 3339 timestamp.
+fn parse_date(value: &str) -> Result<DateTime<Utc>, QueryError> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Ok(datetime.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|datetime| Utc.from_utc_datetime(&datetime))
+        .ok_or_else(|| QueryError::InvalidDate(value.to_string()))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Term {
+    /// Lowercased text to find in the name, the description or a tag.
+    Text(String),
+    Tag(String),
+    /// Lowercased part of the creator email.
+    By(String),
+    /// Inclusive bound on the capture time, falling back to the upload time.
+    After(DateTime<Utc>),
+    /// Exclusive bound on the capture time, falling back to the upload time.
+    Before(DateTime<Utc>),
+    /// Lowercased part of the camera make and model.
+    Camera(String),
+}
+
+impl Term {
+    fn new(field: Option<&str>, value: String) -> Result<Self, QueryError> {
+        Ok(match field {
+            None => Term::Text(value.to_lowercase()),
+            Some("tag") => Term::Tag(value),
+            Some("by") => Term::By(value.to_lowercase()),
+            Some("after") => Term::After(parse_date(&value)?),
+            Some("before") => Term::Before(parse_date(&value)?),
+            Some("camera") => Term::Camera(value.to_lowercase()),
+            Some(field) => return Err(QueryError::UnknownField(field.to_string())),
+        })
+    }
+
+    fn matches(&self, MetadataWithName { metadata, name }: &MetadataWithName) -> bool {
+        match self {
+            Term::Text(text) => {
+                name.to_lowercase().contains(text)
+                    || metadata.description.to_lowercase().contains(text)
+                    || metadata
+                        .tags
+                        .iter()
+                        .any(|tag| tag.to_lowercase().contains(text))
+            }
+            Term::Tag(tag) => metadata.tags.contains(tag),
+            Term::By(by) => metadata.creator_email.to_lowercase().contains(by),
+            Term::After(at) => metadata.taken_or_created_at() >= *at,
+            Term::Before(at) => metadata.taken_or_created_at() < *at,
+            Term::Camera(camera) => match &metadata.exif {
+                Some(exif) => [&exif.make, &exif.model]
+                    .into_iter()
+                    .flatten()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+                    .to_lowercase()
+                    .contains(camera),
+                None => false,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Query {
+    /// Matches if every operand matches, so an empty one matches everything.
+    And(Vec<Query>),
+    Or(Vec<Query>),
+    Not(Box<Query>),
+    Term(Term),
+}
+
+impl Query {
+    pub fn matches(&self, metadata: &MetadataWithName) -> bool {
+        match self {
+            Query::And(operands) => operands.iter().all(|query| query.matches(metadata)),
+            Query::Or(operands) => operands.iter().any(|query| query.matches(metadata)),
+            Query::Not(query) => !query.matches(metadata),
+            Query::Term(term) => term.matches(metadata),
+        }
+    }
+}
+
+/// Groups and negations nested deeper than this are rejected rather than overflowing the stack.
+const MAX_DEPTH: usize = 64;
+
+/// Recursive descent over the tokens, one method per precedence level.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn parse_or(&mut self) -> Result<Query, QueryError> {
+        let mut operands = vec![self.parse_and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            operands.push(self.parse_and()?);
+        }
+        Ok(match operands.len() {
+            1 => operands.remove(0),
+            _ => Query::Or(operands),
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<Query, QueryError> {
+        let mut operands = vec![self.parse_unary()?];
+        loop {
+            match self.peek() {
+                Some(Token::Or | Token::Close) | None => break,
+                Some(Token::And) => self.pos += 1,
+                Some(_) => {}
+            }
+            operands.push(self.parse_unary()?);
+        }
+        Ok(match operands.len() {
+            1 => operands.remove(0),
+            _ => Query::And(operands),
+        })
+    }
+
+    /// Runs `parse` one level deeper.
+    fn nested(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<Query, QueryError>,
+    ) -> Result<Query, QueryError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(QueryError::TooDeep);
+        }
+        self.depth += 1;
+        let query = parse(self);
+        self.depth -= 1;
+        query
+    }
+
+    fn parse_unary(&mut self) -> Result<Query, QueryError> {
+        match self.next() {
+            Some(Token::Not) => {
+                self.nested(|parser| Ok(Query::Not(Box::new(parser.parse_unary()?))))
+            }
+            Some(Token::Open) => {
+                let query = self.nested(Self::parse_or)?;
+                match self.next() {
+                    Some(Token::Close) => Ok(query),
+                    _ => Err(QueryError::UnbalancedParenthesis),
+                }
+            }
+            Some(Token::Term { field, value }) => {
+                Term::new(field.as_deref(), value).map(Query::Term)
+            }
+            Some(Token::Close) => Err(QueryError::UnbalancedParenthesis),
+            Some(Token::And | Token::Or) | None => Err(QueryError::ExpectedTerm),
+        }
+    }
+}
+
+impl FromStr for Query {
+    type Err = QueryError;
+
+    fn from_str(query: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(query)?;
+        if tokens.is_empty() {
+            return Ok(Query::And(Vec::new()));
+        }
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            depth: 0,
+        };
+        let query = parser.parse_or()?;
+        if parser.pos < parser.tokens.len() {
+            return Err(QueryError::UnbalancedParenthesis);
+        }
+        Ok(query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::asset::{Exif, Metadata};
+
+    fn term(field: Option<&str>, value: &str) -> Token {
+        Token::Term {
+            field: field.map(str::to_string),
+            value: value.to_string(),
+        }
+    }
+
+    fn text(value: &str) -> Query {
+        Query::Term(Term::Text(value.to_string()))
+    }
+
+    #[test]
+    fn tokenize_terms_and_operators() {
+        assert_eq!(
+            tokenize(r#"-tag:x (a OR "red car") AND NOT Camera:"X100V" 2022-01-01"#).unwrap(),
+            vec![
+                Token::Not,
+                term(Some("tag"), "x"),
+                Token::Open,
+                term(None, "a"),
+                Token::Or,
+                term(None, "red car"),
+                Token::Close,
+                Token::And,
+                Token::Not,
+                term(Some("camera"), "X100V"),
+                term(None, "2022-01-01"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_negated_field() {
+        assert_eq!(
+            "-tag:x".parse::<Query>().unwrap(),
+            Query::Not(Box::new(Query::Term(Term::Tag("x".to_string()))))
+        );
+    }
+
+    #[test]
+    fn parse_or_binds_looser_than_and() {
+        assert_eq!(
+            "a OR b c".parse::<Query>().unwrap(),
+            Query::Or(vec![text("a"), Query::And(vec![text("b"), text("c")])])
+        );
+        assert_eq!(
+            "(a OR b) AND c".parse::<Query>().unwrap(),
+            Query::And(vec![Query::Or(vec![text("a"), text("b")]), text("c")])
+        );
+    }
+
+    #[test]
+    fn parse_quoted_field() {
+        assert_eq!(
+            r#"camera:"X100V""#.parse::<Query>().unwrap(),
+            Query::Term(Term::Camera("x100v".to_string()))
+        );
+    }
+
+    #[test]
+    fn parse_empty() {
+        assert_eq!("  ".parse::<Query>().unwrap(), Query::And(Vec::new()));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            r#"tag:"x"#.parse::<Query>(),
+            Err(QueryError::UnterminatedQuote)
+        );
+        assert_eq!(
+            "(a OR b".parse::<Query>(),
+            Err(QueryError::UnbalancedParenthesis)
+        );
+        assert_eq!(
+            "a)".parse::<Query>(),
+            Err(QueryError::UnbalancedParenthesis)
+        );
+        assert_eq!(
+            "color:red".parse::<Query>(),
+            Err(QueryError::UnknownField("color".to_string()))
+        );
+        assert_eq!(
+            "after:yesterday".parse::<Query>(),
+            Err(QueryError::InvalidDate("yesterday".to_string()))
+        );
+        assert_eq!("a OR".parse::<Query>(), Err(QueryError::ExpectedTerm));
+        assert_eq!("-".parse::<Query>(), Err(QueryError::ExpectedTerm));
+    }
+
+    #[test]
+    fn parse_too_deep() {
+        let query = format!("{}x", "-".repeat(200_000));
+        assert_eq!(query.parse::<Query>(), Err(QueryError::TooDeep));
+        let query = format!("{}x{}", "(".repeat(200_000), ")".repeat(200_000));
+        assert_eq!(query.parse::<Query>(), Err(QueryError::TooDeep));
+
+        let query = format!("{}x", "-".repeat(MAX_DEPTH));
+        assert!(query.parse::<Query>().is_ok());
+    }
+
+    fn photo(
+        name: &str,
+        tags: &[&str],
+        taken_at: Option<&str>,
+        camera: Option<&str>,
+    ) -> MetadataWithName {
+        let created_at = "2023-06-01T00:00:00Z".parse().unwrap();
+        let exif = (taken_at.is_some() || camera.is_some()).then(|| Exif {
+            taken_at: taken_at.map(|at| at.parse().unwrap()),
+            make: camera.map(|_| "FUJIFILM".to_string()),
+            model: camera.map(str::to_string),
+            ..Default::default()
+        });
+        Metadata {
+            creator_email: format!("{}@example.com", name),
+            created_at,
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            description: format!("Photo of {}", name),
+            exif,
+            content_type: None,
+            size: None,
+            sha256: None,
+        }
+        .with_name(format!("{}.jpg", name))
+    }
+
+    /// Names of the photos which match, in order.
+    fn search<'a>(query: &str, photos: &'a [MetadataWithName]) -> Vec<&'a str> {
+        let query = query.parse::<Query>().unwrap();
+        photos
+            .iter()
+            .filter(|photo| query.matches(photo))
+            .map(|photo| photo.name.as_str())
+            .collect()
+    }
+
+    fn photos() -> Vec<MetadataWithName> {
+        vec![
+            photo(
+                "alice",
+                &["cat"],
+                Some("2022-01-01T12:00:00Z"),
+                Some("X100V"),
+            ),
+            photo("bob", &["dog"], Some("2022-06-01T12:00:00Z"), None),
+            photo("carol", &["cat", "dog"], None, Some("X-T4")),
+        ]
+    }
+
+    #[test]
+    fn match_fields() {
+        let photos = photos();
+        assert_eq!(search("tag:cat", &photos), ["alice.jpg", "carol.jpg"]);
+        assert_eq!(search("tag:Cat", &photos), Vec::<&str>::new());
+        assert_eq!(search("by:BOB@", &photos), ["bob.jpg"]);
+        assert_eq!(
+            search("camera:fujifilm", &photos),
+            ["alice.jpg", "carol.jpg"]
+        );
+        assert_eq!(search(r#"camera:"fujifilm x100v""#, &photos), ["alice.jpg"]);
+        assert_eq!(search("\"of carol\"", &photos), ["carol.jpg"]);
+        assert_eq!(search("DOG", &photos), ["bob.jpg", "carol.jpg"]);
+        assert_eq!(search("", &photos), ["alice.jpg", "bob.jpg", "carol.jpg"]);
+    }
+
+    #[test]
+    fn match_dates() {
+        let photos = photos();
+        // Carol has no capture time, so the upload time is used.
+        assert_eq!(
+            search("after:2022-06-01", &photos),
+            ["bob.jpg", "carol.jpg"]
+        );
+        assert_eq!(search("before:2022-06-01", &photos), ["alice.jpg"]);
+        assert_eq!(
+            search("after:2022-01-01 before:2022-01-02", &photos),
+            ["alice.jpg"]
+        );
+        assert_eq!(search("after:2022-06-01T12:00:01Z", &photos), ["carol.jpg"]);
+    }
+
+    #[test]
+    fn match_operators() {
+        let photos = photos();
+        assert_eq!(search("-tag:dog", &photos), ["alice.jpg"]);
+        assert_eq!(search("NOT tag:cat", &photos), ["bob.jpg"]);
+        assert_eq!(search("tag:cat tag:dog", &photos), ["carol.jpg"]);
+        assert_eq!(search("tag:cat AND -tag:dog", &photos), ["alice.jpg"]);
+        assert_eq!(
+            search("tag:dog OR camera:x100v", &photos),
+            ["alice.jpg", "bob.jpg", "carol.jpg"]
+        );
+        // AND binds tighter than OR.
+        assert_eq!(search("alice OR bob tag:cat", &photos), ["alice.jpg"]);
+        assert_eq!(search("(alice OR bob) tag:dog", &photos), ["bob.jpg"]);
+        assert_eq!(search("-(tag:cat OR tag:dog)", &photos), Vec::<&str>::new());
+    }
+}
@@ -0,0 +1,256 @@
+use std::{
+    io::{ErrorKind, SeekFrom},
+    path::{Component, Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use anyhow::Result;
+use axum::{
+    async_trait,
+    body::{Body, HttpBody},
+};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+    sync::Mutex,
+};
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+use super::{
+    content_range, GetOptions, Object, ObjectInfo, PutCondition, PutOptions, Storage, StorageError,
+};
+
+fn e_tag_from_metadata(metadata: &std::fs::Metadata) -> Option<String> {
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(format!(
+        "\"{:x}-{:x}\"",
+        modified.as_nanos(),
+        metadata.len()
+    ))
+}
+
+/// Stores every object as a file under a root directory, using the key as a relative path.
+pub struct LocalStorage {
+    root: PathBuf,
+    /// Serializes renames so that put conditions are checked against a settled file.
+    write_lock: Mutex<()>,
+}
+
+impl LocalStorage {
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    /// Rejects keys which could resolve outside of the root, such as ones with `..` or absolute
+    /// ones.
+    fn path(&self, key: &str) -> Result<PathBuf, StorageError> {
+        let relative = Path::new(key);
+        let is_relative = relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+        if key.is_empty() || !is_relative {
+            return Err(StorageError::InvalidKey);
+        }
+        Ok(self.root.join(relative))
+    }
+
+    /// Hidden file next to an object which holds its content type.
+    fn content_type_path(path: &Path) -> PathBuf {
+        let file_name = path
+            .file_name()
+            .and_then(|file_name| file_name.to_str())
+            .unwrap_or_default();
+        path.with_file_name(format!(".{}.content-type", file_name))
+    }
+
+    fn key(&self, path: &Path) -> Option<String> {
+        let relative = path.strip_prefix(&self.root).ok()?;
+        let components = relative
+            .components()
+            .map(|component| component.as_os_str().to_str())
+            .collect::<Option<Vec<_>>>()?;
+        Some(components.join("/"))
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    async fn get_object_with(&self, key: &str, options: GetOptions) -> Result<Object> {
+        let path = self.path(key)?;
+        let mut file = match fs::File::open(&path).await {
+            Ok(file) => file,
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                return Err(StorageError::NotFound.into())
+            }
+            Err(error) => return Err(error.into()),
+        };
+        let metadata = file.metadata().await?;
+        let e_tag = e_tag_from_metadata(&metadata);
+        let last_modified = metadata.modified().ok().map(DateTime::<Utc>::from);
+        if options.is_not_modified(e_tag.as_deref(), last_modified) {
+            return Err(StorageError::NotModified.into());
+        }
+        let len = metadata.len();
+        let range = options.byte_range(len)?;
+        let (content_length, body) = match range {
+            Some((start, end)) => {
+                file.seek(SeekFrom::Start(start)).await?;
+                let content_length = end - start + 1;
+                (
+                    content_length,
+                    ReaderStream::new(file.take(content_length)).boxed(),
+                )
+            }
+            None => (len, ReaderStream::new(file).boxed()),
+        };
+        Ok(Object {
+            content_length: content_length as i64,
+            content_type: fs::read_to_string(Self::content_type_path(&path))
+                .await
+                .ok(),
+            content_encoding: None,
+            content_range: range.map(|range| content_range(range, len)),
+            e_tag,
+            last_modified,
+            body,
+        })
+    }
+
+    async fn put_object_with(
+        &self,
+        key: &str,
+        mut body: Body,
+        options: PutOptions,
+    ) -> Result<Option<String>> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        // Write to a hidden temporary file first so that readers never see a partial object. Each
+        // write has its own, as concurrent writes to the same key would truncate a shared one.
+        let file_name = path
+            .file_name()
+            .and_then(|file_name| file_name.to_str())
+            .unwrap_or_default();
+        let temp_path =
+            path.with_file_name(format!(".{}.{}.tmp", file_name, Uuid::new_v4().simple()));
+        let written = async {
+            let mut file = fs::File::create(&temp_path).await?;
+            while let Some(chunk) = body.data().await {
+                file.write_all(&chunk?).await?;
+            }
+            file.sync_all().await?;
+            Ok::<_, anyhow::Error>(())
+        }
+        .await;
+        if let Err(error) = written {
+            let _ = fs::remove_file(&temp_path).await;
+            return Err(error);
+        }
+
+        let _guard = self.write_lock.lock().await;
+        let current = match fs::metadata(&path).await {
+            Ok(metadata) => Some(metadata),
+            Err(error) if error.kind() == ErrorKind::NotFound => None,
+            Err(error) => return Err(error.into()),
+        };
+        let satisfied = match &options.condition {
+            PutCondition::Always => true,
+            PutCondition::IfAbsent => current.is_none(),
+            PutCondition::IfMatch(e_tag) => {
+                current.as_ref().and_then(e_tag_from_metadata).as_ref() == Some(e_tag)
+            }
+        };
+        if !satisfied {
+            fs::remove_file(&temp_path).await?;
+            return Err(StorageError::PreconditionFailed.into());
+        }
+        fs::rename(&temp_path, &path).await?;
+        let content_type_path = Self::content_type_path(&path);
+        match options.content_type {
+            Some(content_type) => fs::write(content_type_path, content_type).await?,
+            None => match fs::remove_file(content_type_path).await {
+                Err(error) if error.kind() != ErrorKind::NotFound => return Err(error.into()),
+                _ => {}
+            },
+        }
+
+        let metadata = fs::metadata(&path).await?;
+        Ok(e_tag_from_metadata(&metadata))
+    }
+
+    async fn head_object(&self, key: &str) -> Result<ObjectInfo> {
+        let metadata = match fs::metadata(self.path(key)?).await {
+            Ok(metadata) => metadata,
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                return Err(StorageError::NotFound.into())
+            }
+            Err(error) => return Err(error.into()),
+        };
+        Ok(ObjectInfo {
+            key: key.to_string(),
+            size: metadata.len() as i64,
+            e_tag: e_tag_from_metadata(&metadata),
+            last_modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+        })
+    }
+
+    async fn delete_object(&self, key: &str) -> Result<()> {
+        let path = self.path(key)?;
+        for path in [Self::content_type_path(&path), path] {
+            match fs::remove_file(path).await {
+                Err(error) if error.kind() != ErrorKind::NotFound => return Err(error.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    async fn list_objects(&self, prefix: &str) -> Result<Vec<ObjectInfo>> {
+        let start = match prefix.rfind('/') {
+            Some(idx) => self.path(&prefix[..idx])?,
+            None => self.root.clone(),
+        };
+
+        let mut objects = Vec::new();
+        let mut directories = vec![start];
+        while let Some(directory) = directories.pop() {
+            let mut entries = match fs::read_dir(&directory).await {
+                Ok(entries) => entries,
+                Err(error) if error.kind() == ErrorKind::NotFound => continue,
+                Err(error) => return Err(error.into()),
+            };
+            while let Some(entry) = entries.next_entry().await? {
+                if entry.file_name().to_string_lossy().starts_with('.') {
+                    continue;
+                }
+                let metadata = entry.metadata().await?;
+                let path = entry.path();
+                if metadata.is_dir() {
+                    directories.push(path);
+                    continue;
+                }
+                let key = match self.key(&path) {
+                    Some(key) if key.starts_with(prefix) => key,
+                    _ => continue,
+                };
+                objects.push(ObjectInfo {
+                    key,
+                    size: metadata.len() as i64,
+                    e_tag: e_tag_from_metadata(&metadata),
+                    last_modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+                });
+            }
+        }
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+
+        Ok(objects)
+    }
+}
@@ -0,0 +1,128 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        RwLock,
+    },
+};
+
+use anyhow::Result;
+use axum::{
+    async_trait,
+    body::{Body, Bytes},
+};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+
+use super::{
+    collect_body, content_range, GetOptions, Object, ObjectInfo, PutCondition, PutOptions, Storage,
+    StorageError,
+};
+
+struct MemoryObject {
+    data: Bytes,
+    content_type: Option<String>,
+    e_tag: String,
+    last_modified: DateTime<Utc>,
+}
+
+/// Keeps every object in process memory. Everything is lost on restart.
+#[derive(Default)]
+pub struct MemoryStorage {
+    objects: RwLock<BTreeMap<String, MemoryObject>>,
+    generation: AtomicU64,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn get_object_with(&self, key: &str, options: GetOptions) -> Result<Object> {
+        let objects = self.objects.read().unwrap();
+        let object = objects.get(key).ok_or(StorageError::NotFound)?;
+        if options.is_not_modified(Some(&object.e_tag), Some(object.last_modified)) {
+            return Err(StorageError::NotModified.into());
+        }
+        let len = object.data.len() as u64;
+        let range = options.byte_range(len)?;
+        let data = match range {
+            Some((start, end)) => object.data.slice(start as usize..=end as usize),
+            None => object.data.clone(),
+        };
+        Ok(Object {
+            content_length: data.len() as i64,
+            content_type: object.content_type.clone(),
+            content_encoding: None,
+            content_range: range.map(|range| content_range(range, len)),
+            e_tag: Some(object.e_tag.clone()),
+            last_modified: Some(object.last_modified),
+            body: futures_util::stream::once(async move { Ok(data) }).boxed(),
+        })
+    }
+
+    async fn put_object_with(
+        &self,
+        key: &str,
+        body: Body,
+        options: PutOptions,
+    ) -> Result<Option<String>> {
+        let data = collect_body(body).await?;
+        let mut objects = self.objects.write().unwrap();
+        let satisfied = match &options.condition {
+            PutCondition::Always => true,
+            PutCondition::IfAbsent => !objects.contains_key(key),
+            PutCondition::IfMatch(e_tag) => {
+                matches!(objects.get(key), Some(object) if &object.e_tag == e_tag)
+            }
+        };
+        if !satisfied {
+            return Err(StorageError::PreconditionFailed.into());
+        }
+        let generation = self.generation.fetch_add(1, Ordering::Relaxed) + 1;
+        let e_tag = format!("\"{:x}\"", generation);
+        objects.insert(
+            key.to_string(),
+            MemoryObject {
+                data,
+                content_type: options.content_type,
+                e_tag: e_tag.clone(),
+                last_modified: Utc::now(),
+            },
+        );
+        Ok(Some(e_tag))
+    }
+
+    async fn head_object(&self, key: &str) -> Result<ObjectInfo> {
+        let objects = self.objects.read().unwrap();
+        let object = objects.get(key).ok_or(StorageError::NotFound)?;
+        Ok(ObjectInfo {
+            key: key.to_string(),
+            size: object.data.len() as i64,
+            e_tag: Some(object.e_tag.clone()),
+            last_modified: Some(object.last_modified),
+        })
+    }
+
+    async fn delete_object(&self, key: &str) -> Result<()> {
+        self.objects.write().unwrap().remove(key);
+        Ok(())
+    }
+
+    async fn list_objects(&self, prefix: &str) -> Result<Vec<ObjectInfo>> {
+        let objects = self.objects.read().unwrap();
+        Ok(objects
+            .range(prefix.to_string()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(key, object)| ObjectInfo {
+                key: key.clone(),
+                size: object.data.len() as i64,
+                e_tag: Some(object.e_tag.clone()),
+                last_modified: Some(object.last_modified),
+            })
+            .collect())
+    }
+}
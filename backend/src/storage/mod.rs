@@ -0,0 +1,259 @@
+mod local;
+mod memory;
+mod s3;
+
+use std::{io, sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Result};
+use axum::{
+    async_trait,
+    body::{Body, Bytes, HttpBody},
+};
+use chrono::{DateTime, Utc};
+use futures_util::{stream::BoxStream, TryStreamExt};
+
+pub use self::{
+    local::LocalStorage,
+    memory::MemoryStorage,
+    s3::{MultipartConfig, S3Storage},
+};
+
+use crate::config::{StorageBackend, CONFIG};
+
+/// Creates the storage backend chosen in the config.
+pub async fn from_config() -> Result<Arc<dyn Storage>> {
+    Ok(match CONFIG.storage_backend {
+        StorageBackend::S3 => {
+            let aws_config = aws_config::load_from_env().await;
+            let s3_client = aws_sdk_s3::Client::new(&aws_config);
+            let bucket = CONFIG
+                .s3_bucket_name
+                .clone()
+                .ok_or_else(|| anyhow!("S3_BUCKET_NAME is required for the s3 storage backend"))?;
+            let multipart = MultipartConfig {
+                threshold: CONFIG.multipart_threshold,
+                part_size: CONFIG.multipart_part_size,
+                concurrency: CONFIG.multipart_concurrency,
+            };
+            Arc::new(S3Storage::new(s3_client, bucket, multipart))
+        }
+        StorageBackend::Local => {
+            Arc::new(LocalStorage::new(CONFIG.local_storage_directory.clone()))
+        }
+        StorageBackend::Memory => Arc::new(MemoryStorage::new()),
+    })
+}
+
+async fn collect_body(mut body: Body) -> Result<Bytes> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        bytes.extend_from_slice(&chunk?);
+    }
+    Ok(bytes.into())
+}
+
+pub type ObjectBody = BoxStream<'static, io::Result<Bytes>>;
+
+pub struct Object {
+    pub body: ObjectBody,
+    /// Length of the body, which is only a part of the object if a range was requested.
+    pub content_length: i64,
+    pub content_type: Option<String>,
+    pub content_encoding: Option<String>,
+    /// Set if a range was requested, e.g. `bytes 0-99/1000`.
+    pub content_range: Option<String>,
+    pub e_tag: Option<String>,
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+impl Object {
+    pub async fn into_bytes(self) -> Result<Vec<u8>> {
+        let bytes = self
+            .body
+            .try_fold(Vec::new(), |mut acc, chunk| async move {
+                acc.extend_from_slice(&chunk);
+                Ok(acc)
+            })
+            .await?;
+        Ok(bytes)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum StorageError {
+    #[error("object not found")]
+    NotFound,
+    #[error("precondition failed")]
+    PreconditionFailed,
+    #[error("not modified")]
+    NotModified,
+    #[error("range not satisfiable")]
+    RangeNotSatisfiable,
+    #[error("invalid key")]
+    InvalidKey,
+}
+
+/// Options of a read, mirroring the HTTP headers of the same names.
+#[derive(Debug, Clone, Default)]
+pub struct GetOptions {
+    /// Value of a `Range` header. Only a single range is honored, otherwise the whole object is
+    /// read.
+    pub range: Option<String>,
+    /// Fails with [`StorageError::NotModified`] if the ETag of the object is one of these.
+    pub if_none_match: Option<String>,
+    /// Fails with [`StorageError::NotModified`] if the object is not modified since then. Ignored
+    /// if `if_none_match` is set.
+    pub if_modified_since: Option<DateTime<Utc>>,
+}
+
+impl GetOptions {
+    fn is_not_modified(&self, e_tag: Option<&str>, last_modified: Option<DateTime<Utc>>) -> bool {
+        if let Some(if_none_match) = &self.if_none_match {
+            let e_tag = match e_tag {
+                Some(e_tag) => e_tag.trim_start_matches("W/"),
+                None => return false,
+            };
+            return if_none_match
+                .split(',')
+                .map(str::trim)
+                .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == e_tag);
+        }
+        match (self.if_modified_since, last_modified) {
+            (Some(since), Some(last_modified)) => last_modified.timestamp() <= since.timestamp(),
+            _ => false,
+        }
+    }
+
+    /// Resolves the range against the length of the object into inclusive offsets.
+    fn byte_range(&self, len: u64) -> Result<Option<(u64, u64)>, StorageError> {
+        let range = match self
+            .range
+            .as_deref()
+            .and_then(|range| range.strip_prefix("bytes="))
+        {
+            Some(range) if !range.contains(',') => range.trim(),
+            _ => return Ok(None),
+        };
+        let (start, end) = match range.split_once('-') {
+            Some(range) => range,
+            None => return Ok(None),
+        };
+        let (start, end) = match (start.parse::<u64>(), end.parse::<u64>()) {
+            (Ok(start), Ok(end)) if start <= end => (start, end.min(len.saturating_sub(1))),
+            (Ok(start), Err(_)) if end.is_empty() => (start, len.saturating_sub(1)),
+            (Err(_), Ok(suffix)) if start.is_empty() && suffix > 0 => {
+                (len.saturating_sub(suffix), len.saturating_sub(1))
+            }
+            _ => return Ok(None),
+        };
+        if start >= len {
+            return Err(StorageError::RangeNotSatisfiable);
+        }
+        Ok(Some((start, end)))
+    }
+}
+
+fn content_range((start, end): (u64, u64), len: u64) -> String {
+    format!("bytes {}-{}/{}", start, end, len)
+}
+
+/// Condition which must hold for a write to take place. A failed condition is reported as
+/// [`StorageError::PreconditionFailed`].
+#[derive(Debug, Clone, Default)]
+pub enum PutCondition {
+    #[default]
+    Always,
+    /// The object must not exist yet.
+    IfAbsent,
+    /// The object must exist with the given ETag.
+    IfMatch(String),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PutOptions {
+    pub condition: PutCondition,
+    pub content_type: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ObjectInfo {
+    pub key: String,
+    pub size: i64,
+    pub e_tag: Option<String>,
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+/// Copies an object by reading it and writing it back, which every backend supports.
+pub async fn copy_object_by_streaming<S>(
+    storage: &S,
+    from: &str,
+    to: &str,
+    condition: PutCondition,
+) -> Result<()>
+where
+    S: Storage + ?Sized,
+{
+    let object = storage.get_object(from).await?;
+    let options = PutOptions {
+        condition,
+        content_type: object.content_type,
+    };
+    storage
+        .put_object_with(to, Body::wrap_stream(object.body), options)
+        .await?;
+    Ok(())
+}
+
+/// Flat key-value object store which holds every photo and metadata.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    async fn get_object(&self, key: &str) -> Result<Object> {
+        self.get_object_with(key, GetOptions::default()).await
+    }
+
+    async fn get_object_with(&self, key: &str, options: GetOptions) -> Result<Object>;
+
+    /// Writes an object and returns its new ETag, if the backend reports one.
+    async fn put_object_with(
+        &self,
+        key: &str,
+        body: Body,
+        options: PutOptions,
+    ) -> Result<Option<String>>;
+
+    /// Reads only the object information, failing with [`StorageError::NotFound`] if missing.
+    async fn head_object(&self, key: &str) -> Result<ObjectInfo>;
+
+    async fn delete_object(&self, key: &str) -> Result<()>;
+
+    /// Copies an object with its content type, writing `to` on `condition`.
+    async fn copy_object(&self, from: &str, to: &str, condition: PutCondition) -> Result<()> {
+        copy_object_by_streaming(self, from, to, condition).await
+    }
+
+    /// Lists every object whose key starts with `prefix`, sorted by key.
+    async fn list_objects(&self, prefix: &str) -> Result<Vec<ObjectInfo>>;
+
+    /// Makes a URL which anyone can GET the object from until it expires. Backends which are not
+    /// reachable by browsers return `None`.
+    async fn presign_get_object(
+        &self,
+        _key: &str,
+        _expires_in: Duration,
+    ) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Makes a URL which anyone can PUT the object to until it expires, with exactly the given
+    /// `Content-Type` and `Content-Length`. Backends which are not reachable by browsers return
+    /// `None`.
+    async fn presign_put_object(
+        &self,
+        _key: &str,
+        _content_type: &str,
+        _content_length: i64,
+        _expires_in: Duration,
+    ) -> Result<Option<String>> {
+        Ok(None)
+    }
+}
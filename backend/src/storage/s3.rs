@@ -0,0 +1,408 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use aws_sdk_s3::{
+    model::{CompletedMultipartUpload, CompletedPart},
+    presigning::config::PresigningConfig,
+    types::{ByteStream, SdkError},
+    Client,
+};
+use axum::{
+    async_trait,
+    body::{Body, HttpBody},
+};
+use chrono::{DateTime, TimeZone, Utc};
+use futures_util::{StreamExt, TryStreamExt};
+use http::{
+    header::{self, HeaderName},
+    HeaderValue, StatusCode,
+};
+
+use super::{
+    copy_object_by_streaming, GetOptions, Object, ObjectInfo, PutCondition, PutOptions, Storage,
+    StorageError,
+};
+
+/// Largest object which CopyObject accepts.
+const MAX_COPY_SIZE: i64 = 5 * 1024 * 1024 * 1024;
+
+/// Turns status codes which callers care about into [`StorageError`].
+fn into_storage_error<E>(error: SdkError<E>) -> anyhow::Error
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    if let SdkError::ServiceError { raw, .. } = &error {
+        match raw.http().status() {
+            StatusCode::NOT_FOUND => return StorageError::NotFound.into(),
+            StatusCode::NOT_MODIFIED => return StorageError::NotModified.into(),
+            StatusCode::RANGE_NOT_SATISFIABLE => return StorageError::RangeNotSatisfiable.into(),
+            // S3 answers 409 when a concurrent conditional write is in progress.
+            StatusCode::PRECONDITION_FAILED | StatusCode::CONFLICT => {
+                return StorageError::PreconditionFailed.into()
+            }
+            _ => {}
+        }
+    }
+    error.into()
+}
+
+/// Everything but unreserved characters and `/`, which separates the bucket from the key.
+const COPY_SOURCE_ENCODE_SET: &percent_encoding::AsciiSet = &percent_encoding::NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~')
+    .remove(b'/');
+
+fn to_chrono(dt: &aws_sdk_s3::types::DateTime) -> Option<DateTime<Utc>> {
+    Utc.timestamp_opt(dt.secs(), dt.subsec_nanos()).single()
+}
+
+fn condition_header(condition: PutCondition) -> Result<Option<(HeaderName, HeaderValue)>> {
+    Ok(match condition {
+        PutCondition::Always => None,
+        PutCondition::IfAbsent => Some((header::IF_NONE_MATCH, HeaderValue::from_static("*"))),
+        PutCondition::IfMatch(e_tag) => Some((header::IF_MATCH, HeaderValue::from_str(&e_tag)?)),
+    })
+}
+
+/// Reads from the body until `buf` holds at least `size` bytes or the body ends.
+async fn fill(body: &mut Body, buf: &mut Vec<u8>, size: usize) -> Result<()> {
+    while buf.len() < size {
+        match body.data().await {
+            Some(chunk) => buf.extend_from_slice(&chunk?),
+            None => break,
+        }
+    }
+    Ok(())
+}
+
+/// S3 rejects parts smaller than this, except the last one.
+const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy)]
+pub struct MultipartConfig {
+    /// Bodies longer than this are uploaded in parts.
+    pub threshold: usize,
+    pub part_size: usize,
+    /// Parts uploaded at the same time.
+    pub concurrency: usize,
+}
+
+/// Aborts a multipart upload unless disarmed, so that neither an error nor a dropped request
+/// leaves parts behind in the bucket.
+struct AbortGuard {
+    client: Client,
+    bucket: String,
+    key: String,
+    upload_id: String,
+    armed: bool,
+}
+
+impl Drop for AbortGuard {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        let fut = self
+            .client
+            .abort_multipart_upload()
+            .bucket(&self.bucket)
+            .key(&self.key)
+            .upload_id(&self.upload_id)
+            .send();
+        let key = self.key.clone();
+        tokio::spawn(async move {
+            if let Err(error) = fut.await {
+                tracing::warn!(%key, %error, "failed to abort multipart upload");
+            }
+        });
+    }
+}
+
+pub struct S3Storage {
+    client: Client,
+    bucket: String,
+    multipart: MultipartConfig,
+}
+
+impl S3Storage {
+    pub fn new(client: Client, bucket: String, multipart: MultipartConfig) -> Self {
+        Self {
+            client,
+            bucket,
+            multipart,
+        }
+    }
+
+    async fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: i32,
+        part: Vec<u8>,
+    ) -> Result<CompletedPart> {
+        let resp = self
+            .client
+            .upload_part()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .body(ByteStream::from(part))
+            .send()
+            .await
+            .map_err(into_storage_error)?;
+        Ok(CompletedPart::builder()
+            .part_number(part_number)
+            .set_e_tag(resp.e_tag().map(str::to_string))
+            .build())
+    }
+
+    /// Uploads `head` followed by the rest of `body` in parts of the configured size.
+    async fn put_object_multipart(
+        &self,
+        key: &str,
+        body: Body,
+        head: Vec<u8>,
+        options: PutOptions,
+    ) -> Result<Option<String>> {
+        let resp = self
+            .client
+            .create_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .set_content_type(options.content_type)
+            .send()
+            .await
+            .map_err(into_storage_error)?;
+        let upload_id = resp
+            .upload_id()
+            .ok_or_else(|| anyhow!("no upload ID for multipart upload"))?
+            .to_string();
+        let mut guard = AbortGuard {
+            client: self.client.clone(),
+            bucket: self.bucket.clone(),
+            key: key.to_string(),
+            upload_id: upload_id.clone(),
+            armed: true,
+        };
+
+        let part_size = self.multipart.part_size.max(MIN_PART_SIZE);
+        let parts = futures_util::stream::try_unfold(
+            (body, head, 1),
+            |(mut body, mut buf, part_number)| async move {
+                fill(&mut body, &mut buf, part_size).await?;
+                if buf.is_empty() {
+                    return Ok(None);
+                }
+                let rest = buf.split_off(buf.len().min(part_size));
+                let part = std::mem::replace(&mut buf, rest);
+                Ok::<_, anyhow::Error>(Some(((part_number, part), (body, buf, part_number + 1))))
+            },
+        )
+        .map_ok(|(part_number, part)| self.upload_part(key, &upload_id, part_number, part))
+        .try_buffered(self.multipart.concurrency.max(1))
+        .try_collect::<Vec<_>>()
+        .await?;
+
+        let mut complete = self
+            .client
+            .complete_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(&upload_id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(parts))
+                    .build(),
+            )
+            .customize()
+            .await?;
+        if let Some((name, value)) = condition_header(options.condition)? {
+            complete = complete.mutate_request(move |req| {
+                req.headers_mut().insert(name.clone(), value.clone());
+            });
+        }
+        let resp = complete.send().await.map_err(into_storage_error)?;
+        guard.armed = false;
+
+        Ok(resp.e_tag().map(str::to_string))
+    }
+}
+
+#[async_trait]
+impl Storage for S3Storage {
+    async fn get_object_with(&self, key: &str, options: GetOptions) -> Result<Object> {
+        let resp = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .set_range(options.range)
+            .set_if_none_match(options.if_none_match)
+            .set_if_modified_since(
+                options
+                    .if_modified_since
+                    .map(|since| aws_sdk_s3::types::DateTime::from_secs(since.timestamp())),
+            )
+            .send()
+            .await
+            .map_err(into_storage_error)?;
+        Ok(Object {
+            content_length: resp.content_length(),
+            content_type: resp.content_type().map(str::to_string),
+            content_encoding: resp.content_encoding().map(str::to_string),
+            content_range: resp.content_range().map(str::to_string),
+            e_tag: resp.e_tag().map(str::to_string),
+            last_modified: resp.last_modified().and_then(to_chrono),
+            body: resp.body.map_err(std::io::Error::other).boxed(),
+        })
+    }
+
+    async fn put_object_with(
+        &self,
+        key: &str,
+        mut body: Body,
+        options: PutOptions,
+    ) -> Result<Option<String>> {
+        // Peek one byte past the threshold to find out whether the body fits in a single put.
+        let mut head = Vec::new();
+        fill(&mut body, &mut head, self.multipart.threshold + 1).await?;
+        if head.len() > self.multipart.threshold {
+            return self.put_object_multipart(key, body, head, options).await;
+        }
+
+        let mut operation = self
+            .client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .body(ByteStream::from(head))
+            .set_content_type(options.content_type)
+            .customize()
+            .await?;
+        if let Some((name, value)) = condition_header(options.condition)? {
+            operation = operation.mutate_request(move |req| {
+                req.headers_mut().insert(name.clone(), value.clone());
+            });
+        }
+        let resp = operation.send().await.map_err(into_storage_error)?;
+        Ok(resp.e_tag().map(str::to_string))
+    }
+
+    async fn head_object(&self, key: &str) -> Result<ObjectInfo> {
+        let resp = self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(into_storage_error)?;
+        Ok(ObjectInfo {
+            key: key.to_string(),
+            size: resp.content_length(),
+            e_tag: resp.e_tag().map(str::to_string),
+            last_modified: resp.last_modified().and_then(to_chrono),
+        })
+    }
+
+    async fn delete_object(&self, key: &str) -> Result<()> {
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn copy_object(&self, from: &str, to: &str, condition: PutCondition) -> Result<()> {
+        // Bigger objects would need a multipart copy, and are rare enough to go through here.
+        if self.head_object(from).await?.size > MAX_COPY_SIZE {
+            return copy_object_by_streaming(self, from, to, condition).await;
+        }
+
+        let copy_source = format!(
+            "{}/{}",
+            self.bucket,
+            percent_encoding::utf8_percent_encode(from, COPY_SOURCE_ENCODE_SET)
+        );
+        let mut operation = self
+            .client
+            .copy_object()
+            .bucket(&self.bucket)
+            .key(to)
+            .copy_source(copy_source)
+            .customize()
+            .await?;
+        if let Some((name, value)) = condition_header(condition)? {
+            operation = operation.mutate_request(move |req| {
+                req.headers_mut().insert(name.clone(), value.clone());
+            });
+        }
+        operation.send().await.map_err(into_storage_error)?;
+        Ok(())
+    }
+
+    async fn list_objects(&self, prefix: &str) -> Result<Vec<ObjectInfo>> {
+        self.client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .prefix(prefix)
+            .into_paginator()
+            .send()
+            .err_into::<anyhow::Error>()
+            .map_ok(|output| {
+                futures_util::stream::iter(
+                    output
+                        .contents
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(Result::<_, anyhow::Error>::Ok),
+                )
+            })
+            .try_flatten()
+            .try_filter_map(|object| async move {
+                Ok(object.key().map(|key| ObjectInfo {
+                    key: key.to_string(),
+                    size: object.size(),
+                    e_tag: object.e_tag().map(str::to_string),
+                    last_modified: object.last_modified().and_then(to_chrono),
+                }))
+            })
+            .try_collect()
+            .await
+    }
+
+    async fn presign_get_object(&self, key: &str, expires_in: Duration) -> Result<Option<String>> {
+        let req = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .presigned(PresigningConfig::expires_in(expires_in)?)
+            .await?;
+        Ok(Some(req.uri().to_string()))
+    }
+
+    async fn presign_put_object(
+        &self,
+        key: &str,
+        content_type: &str,
+        content_length: i64,
+        expires_in: Duration,
+    ) -> Result<Option<String>> {
+        let req = self
+            .client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .content_type(content_type)
+            .content_length(content_length)
+            .presigned(PresigningConfig::expires_in(expires_in)?)
+            .await?;
+        Ok(Some(req.uri().to_string()))
+    }
+}
@@ -0,0 +1,104 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Album {
+    pub owner_email: String,
+    pub created_at: DateTime<Utc>,
+    pub title: String,
+    pub description: String,
+    /// One of `photos`, shown for the album.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cover: Option<String>,
+    /// Names of photos in the order they are shown.
+    #[serde(default)]
+    pub photos: Vec<String>,
+}
+
+impl Album {
+    pub fn with_id(self, id: String) -> AlbumWithId {
+        AlbumWithId { album: self, id }
+    }
+
+    /// Removes a photo, and the cover if it was the photo. Returns whether the photo was in the
+    /// album.
+    pub fn remove_photo(&mut self, name: &str) -> bool {
+        if self.cover.as_deref() == Some(name) {
+            self.cover = None;
+        }
+        let len = self.photos.len();
+        self.photos.retain(|photo| photo != name);
+        self.photos.len() != len
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AlbumWithId {
+    #[serde(flatten)]
+    pub album: Album,
+    pub id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlbumCreationRequest {
+    pub title: String,
+    pub description: String,
+}
+
+impl AlbumCreationRequest {
+    pub fn create(self, owner_email: String) -> Album {
+        let AlbumCreationRequest { title, description } = self;
+        Album {
+            owner_email,
+            created_at: Utc::now(),
+            title,
+            description,
+            cover: None,
+            photos: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlbumUpdateRequest {
+    pub title: String,
+    pub description: String,
+    pub cover: Option<String>,
+}
+
+impl AlbumUpdateRequest {
+    pub fn update(self, album: Album) -> Album {
+        let AlbumUpdateRequest {
+            title,
+            description,
+            cover,
+        } = self;
+        Album {
+            title,
+            description,
+            cover,
+            ..album
+        }
+    }
+}
+
+/// Replaces the photos of an album, which adds, removes and reorders them at once.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlbumPhotosRequest {
+    pub photos: Vec<String>,
+}
+
+impl AlbumPhotosRequest {
+    pub fn update(self, album: Album) -> Album {
+        let cover = album.cover.filter(|cover| self.photos.contains(cover));
+        Album {
+            cover,
+            photos: self.photos,
+            ..album
+        }
+    }
+}
@@ -3,6 +3,23 @@ use std::collections::BTreeSet;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+/// Subset of EXIF recorded on upload. Rationals are kept in their display form, e.g. `1/250`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Exif {
+    pub taken_at: Option<DateTime<Utc>>,
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub lens_model: Option<String>,
+    pub exposure_time: Option<String>,
+    pub f_number: Option<String>,
+    pub iso: Option<u32>,
+    pub focal_length: Option<String>,
+    pub orientation: Option<u16>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Metadata {
@@ -10,6 +27,23 @@ pub struct Metadata {
     pub created_at: DateTime<Utc>,
     pub tags: BTreeSet<String>,
     pub description: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exif: Option<Exif>,
+    /// Detected on upload. Missing for photos uploaded before detection existed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    /// Size of the photo in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<i64>,
+    /// Hex-encoded SHA-256 of the photo, to find out duplicates. Missing for direct uploads.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+/// Names become part of object keys, so they must not escape their prefix. Neither may they start
+/// with a dot, which the local storage keeps for its own files.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('.') && !name.contains('/') && !name.contains("..")
 }
 
 impl Metadata {
@@ -19,11 +53,20 @@ impl Metadata {
             name,
         }
     }
+
+    pub fn taken_at(&self) -> Option<DateTime<Utc>> {
+        self.exif.as_ref().and_then(|exif| exif.taken_at)
+    }
+
+    /// Capture time, or upload time for photos without one.
+    pub fn taken_or_created_at(&self) -> DateTime<Utc> {
+        self.taken_at().unwrap_or(self.created_at)
+    }
 }
 
 impl PartialOrd for Metadata {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        self.created_at.partial_cmp(&other.created_at)
+        Some(self.cmp(other))
     }
 }
 
@@ -58,6 +101,10 @@ impl MetadataCreationRequest {
             created_at,
             tags,
             description,
+            exif: None,
+            content_type: None,
+            size: None,
+            sha256: None,
         }
     }
 }
@@ -75,6 +122,10 @@ impl MetadataUpdateRequest {
         Metadata {
             creator_email,
             created_at,
+            exif,
+            content_type,
+            size,
+            sha256,
             ..
         }: Metadata,
     ) -> Metadata {
@@ -85,13 +136,58 @@ impl MetadataUpdateRequest {
             created_at,
             tags,
             description,
+            exif,
+            content_type,
+            size,
+            sha256,
         }
     }
 }
 
-#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash, Serialize)]
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash, Deserialize, Serialize)]
 pub struct MetadataWithName {
     #[serde(flatten)]
     pub metadata: Metadata,
     pub name: String,
 }
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashedPhoto {
+    #[serde(flatten)]
+    pub metadata: MetadataWithName,
+    pub deleted_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    #[default]
+    Uploaded,
+    /// Capture time, falling back to upload time.
+    Taken,
+    Name,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl MetadataWithName {
+    /// Compares by the given key, breaking ties by name so that the order is always total.
+    pub fn cmp_by(&self, other: &Self, key: SortKey) -> std::cmp::Ordering {
+        match key {
+            SortKey::Uploaded => self.metadata.created_at.cmp(&other.metadata.created_at),
+            SortKey::Taken => self
+                .metadata
+                .taken_or_created_at()
+                .cmp(&other.metadata.taken_or_created_at()),
+            SortKey::Name => std::cmp::Ordering::Equal,
+        }
+        .then_with(|| self.name.cmp(&other.name))
+    }
+}
@@ -4,8 +4,48 @@ pub enum Error {
     UserNotAuthorized,
     #[error("user not allowed")]
     UserNotAllowed,
+    #[error("permission denied")]
+    PermissionDenied,
     #[error("unexpected error while authorizing")]
     Authorize,
+    #[error("OAuth state mismatch")]
+    OAuthStateMismatch,
+    #[error("authorization provider not found")]
+    ProviderNotFound,
+    #[error("invalid photo name")]
+    InvalidName,
+    #[error("photo already exists")]
+    PhotoAlreadyExists,
+    #[error("photo with the same name is already in the trash")]
+    PhotoAlreadyInTrash,
+    #[error("photo has been changed in the meantime")]
+    PreconditionFailed,
+    #[error("range not satisfiable")]
+    RangeNotSatisfiable,
+    #[error("photo not found")]
+    PhotoNotFound,
+    #[error("photo is too large")]
+    PhotoTooLarge,
+    #[error("invalid photo size")]
+    InvalidPhotoSize,
+    #[error("unsupported content type")]
+    UnsupportedContentType,
+    #[error("revision not found")]
+    RevisionNotFound,
+    #[error("album not found")]
+    AlbumNotFound,
+    #[error("invalid album")]
+    InvalidAlbum,
+    #[error("share not found")]
+    ShareNotFound,
+    #[error("invalid share password")]
+    InvalidSharePassword,
+    #[error("invalid share")]
+    InvalidShare,
+    #[error("direct upload is not available")]
+    DirectUploadUnavailable,
+    #[error("invalid query: {0}")]
+    InvalidQuery(crate::search::QueryError),
     #[error("failed to request to S3: {0}")]
     S3(anyhow::Error),
 }
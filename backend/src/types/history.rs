@@ -0,0 +1,79 @@
+use std::collections::BTreeSet;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::asset::Metadata;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DescriptionChange {
+    pub before: String,
+    pub after: String,
+}
+
+/// One write of the editable fields of a metadata, kept as a diff so that a revision can be
+/// reached from the current metadata by undoing every later entry.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub editor_email: String,
+    pub edited_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub added_tags: BTreeSet<String>,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub removed_tags: BTreeSet<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<DescriptionChange>,
+}
+
+impl HistoryEntry {
+    /// Diff from `before` to `after`, where a missing `before` is a photo without tags and
+    /// description, as for an upload.
+    pub fn between(editor_email: String, before: Option<&Metadata>, after: &Metadata) -> Self {
+        let empty = BTreeSet::new();
+        let (before_tags, before_description) = match before {
+            Some(before) => (&before.tags, before.description.as_str()),
+            None => (&empty, ""),
+        };
+        let description = (before_description != after.description).then(|| DescriptionChange {
+            before: before_description.to_string(),
+            after: after.description.clone(),
+        });
+        Self {
+            editor_email,
+            edited_at: Utc::now(),
+            added_tags: after.tags.difference(before_tags).cloned().collect(),
+            removed_tags: before_tags.difference(&after.tags).cloned().collect(),
+            description,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added_tags.is_empty() && self.removed_tags.is_empty() && self.description.is_none()
+    }
+
+    pub fn undo(&self, metadata: &mut Metadata) {
+        for tag in &self.added_tags {
+            metadata.tags.remove(tag);
+        }
+        metadata.tags.extend(self.removed_tags.iter().cloned());
+        if let Some(description) = &self.description {
+            metadata.description = description.before.clone();
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryRevision {
+    /// Index in the history, starting from zero.
+    pub revision: usize,
+    #[serde(flatten)]
+    pub entry: HistoryEntry,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevertRequest {
+    pub revision: usize,
+}
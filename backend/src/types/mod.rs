@@ -1,2 +1,5 @@
+pub mod album;
 pub mod asset;
 pub mod error;
+pub mod history;
+pub mod share;
@@ -0,0 +1,119 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+/// What a share grants read-only access to.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ShareTarget {
+    Photo {
+        name: String,
+    },
+    /// Every photo with the tag, including ones tagged after the share was made.
+    Tag {
+        tag: String,
+    },
+    Album {
+        id: String,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Share {
+    pub owner_email: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub target: ShareTarget,
+    /// Salt and SHA-256 of the password, as `{salt}:{hash}`. This only keeps the password out of
+    /// plain sight, as anyone who can read the bucket can read the photos anyway.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+}
+
+fn hash_password(salt: &str, password: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt);
+    hasher.update(password);
+    format!("{:x}", hasher.finalize())
+}
+
+impl Share {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now()
+    }
+
+    pub fn check_password(&self, password: Option<&str>) -> bool {
+        match (&self.password, password) {
+            (None, _) => true,
+            (Some(stored), Some(password)) => match stored.split_once(':') {
+                Some((salt, hash)) => hash_password(salt, password) == hash,
+                None => false,
+            },
+            (Some(_), None) => false,
+        }
+    }
+
+    pub fn with_id(self, id: String) -> ShareWithId {
+        let Share {
+            owner_email,
+            created_at,
+            expires_at,
+            target,
+            password,
+        } = self;
+        ShareWithId {
+            id,
+            owner_email,
+            created_at,
+            expires_at,
+            target,
+            has_password: password.is_some(),
+        }
+    }
+}
+
+/// A share as shown to its owner, without the password.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShareWithId {
+    pub id: String,
+    pub owner_email: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub target: ShareTarget,
+    pub has_password: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShareCreationRequest {
+    pub target: ShareTarget,
+    pub expires_at: DateTime<Utc>,
+    pub password: Option<String>,
+}
+
+impl ShareCreationRequest {
+    pub fn create(self, owner_email: String) -> Share {
+        let ShareCreationRequest {
+            target,
+            expires_at,
+            password,
+        } = self;
+        let password = password
+            .filter(|password| !password.is_empty())
+            .map(|password| {
+                let salt = Uuid::new_v4().simple().to_string();
+                let hash = hash_password(&salt, &password);
+                format!("{}:{}", salt, hash)
+            });
+        Share {
+            owner_email,
+            created_at: Utc::now(),
+            expires_at,
+            target,
+            password,
+        }
+    }
+}
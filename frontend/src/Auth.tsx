@@ -3,12 +3,34 @@ import { createContext, ReactNode, useContext } from "react";
 import { Navigate, useLocation } from "react-router-dom";
 
 import { User } from "./HttpTypes";
-import { useUserFromQuery } from "./QueryHooks";
+import { useAuthProviders, useUserFromQuery } from "./QueryHooks";
 import Spinner from "./Spinner";
 
 const UserContext = createContext<User>(undefined!);
 export const useUser = (): User => useContext(UserContext);
 
+function Login({ redirect }: { redirect: string }) {
+  const { data: providers, isLoading } = useAuthProviders();
+
+  if (isLoading) {
+    return <Spinner />;
+  }
+
+  return (
+    <div className="flex flex-col items-center p-5">
+      {(providers || []).map((provider) => (
+        <a
+          key={provider.id}
+          href={`/auth/login/${provider.id}?redirect=${redirect}`}
+          className="rounded-full px-5 py-2 mb-2 bg-gray-100 hover:opacity-75"
+        >
+          Log in with {provider.displayName}
+        </a>
+      ))}
+    </div>
+  );
+}
+
 export function AuthRequired({ children }: { children: ReactNode }) {
   const location = useLocation();
   const { data: user, isLoading, error } = useUserFromQuery();
@@ -21,8 +43,7 @@ export function AuthRequired({ children }: { children: ReactNode }) {
     if (isAxiosError(error)) {
       const status = error.response?.status;
       if (status === 401) {
-        window.location.replace(`/auth/github?redirect=${location.pathname}`);
-        return <div>Authorizing...</div>;
+        return <Login redirect={location.pathname} />;
       } else if (status === 403) {
         return <div>Forbidden</div>;
       }
@@ -13,18 +13,19 @@ function EditPhoto() {
   const [description, setDescription] = useState(metadata?.description || "");
   const [tags, setTags] = useState(metadata?.tags?.join(",") || "");
 
-  const { mutate: edit, isLoading: isEditLoading } = useEditPhotoMutation(
-    name,
-    {
-      onSuccess: () => {
-        if (name) {
-          navigate(`/photo/${name}`);
-        } else {
-          navigate(`/tag`);
-        }
-      },
-    }
-  );
+  const {
+    mutate: edit,
+    isLoading: isEditLoading,
+    error: editError,
+  } = useEditPhotoMutation(name, metadata?.eTag, {
+    onSuccess: () => {
+      if (name) {
+        navigate(`/photo/${name}`);
+      } else {
+        navigate(`/tag`);
+      }
+    },
+  });
 
   const onSubmit = (event: FormEvent<HTMLFormElement>) => {
     event.preventDefault();
@@ -62,6 +63,11 @@ function EditPhoto() {
             onChange={(event) => setTags(event.target.value)}
           />
         </div>
+        {editError?.response?.status === 412 && (
+          <p className="mb-2">
+            Someone else has edited this photo. Reload the page and try again.
+          </p>
+        )}
         <div>
           {isEditLoading ? (
             <Spinner />
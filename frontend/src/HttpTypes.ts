@@ -1,3 +1,8 @@
+export interface AuthProvider {
+  id: string;
+  displayName: string;
+}
+
 export interface User {
   primaryEmail: string;
   emails: string[];
@@ -8,10 +13,15 @@ export interface Metadata {
   createdAt: string;
   tags: string[];
   description: string;
+  contentType?: string;
+  size?: number;
+  sha256?: string;
 }
 
 export type MetadataWithName = Metadata & { name: string };
 
+export type MetadataWithETag = Metadata & { eTag?: string };
+
 export interface MetadataCreationRequest {
   tags: string;
   description: string;
@@ -23,12 +33,15 @@ export interface UploadReq {
   metadata: MetadataCreationRequest;
 }
 
-export type TagsWithSample = Map<String, MetadataWithName>;
-
-export interface SearchReq {
-  token: string;
+export interface UploadUrl {
+  method: string;
+  url: string;
+  headers: Record<string, string>;
+  uploadId: string;
 }
 
+export type TagsWithSample = Map<String, MetadataWithName>;
+
 export interface MetadataUpdateRequest {
   tags: string;
   description: string;
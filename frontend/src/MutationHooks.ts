@@ -1,4 +1,4 @@
-import { AxiosError } from "axios";
+import { AxiosError, isAxiosError } from "axios";
 import {
   useMutation,
   UseMutationOptions,
@@ -8,9 +8,8 @@ import {
 import { useAxiosClient } from "./AxiosContext";
 import {
   MetadataUpdateRequest,
-  MetadataWithName,
-  SearchReq,
   UploadReq,
+  UploadUrl,
 } from "./HttpTypes";
 
 type MutationRet<T, Ret = void> = UseMutationResult<
@@ -29,25 +28,42 @@ export function useUploadMutation(
 ): MutationRet<UploadReq, string> {
   const client = useAxiosClient();
   return useMutation(async (payload: UploadReq) => {
-    await client.post(`/api/photo/${payload.name}`, payload.file, {
-      params: payload.metadata,
-    });
-    return payload.name;
-  }, options);
-}
+    let uploadUrl: UploadUrl | undefined;
+    try {
+      const resp = await client.post<UploadUrl>(
+        `/api/photo/${payload.name}/upload-url`,
+        { contentType: payload.file.type, size: payload.file.size }
+      );
+      uploadUrl = resp.data;
+    } catch (error) {
+      // Direct upload is not available, so upload through the server.
+      if (!isAxiosError(error) || error.response?.status !== 501) {
+        throw error;
+      }
+    }
 
-export function useSearchMutation(
-  options?: MutationOption<SearchReq, MetadataWithName[]>
-): MutationRet<SearchReq, MetadataWithName[]> {
-  const client = useAxiosClient();
-  return useMutation(async (payload: SearchReq) => {
-    const resp = await client.post<MetadataWithName[]>("/api/search", payload);
-    return resp.data;
+    if (uploadUrl) {
+      await client.request({
+        method: uploadUrl.method,
+        url: uploadUrl.url,
+        data: payload.file,
+        headers: uploadUrl.headers,
+      });
+      await client.post(`/api/photo/${payload.name}/commit`, undefined, {
+        params: { ...payload.metadata, uploadId: uploadUrl.uploadId },
+      });
+    } else {
+      await client.post(`/api/photo/${payload.name}`, payload.file, {
+        params: payload.metadata,
+      });
+    }
+    return payload.name;
   }, options);
 }
 
 export function useEditPhotoMutation(
   name: string | undefined,
+  eTag: string | undefined,
   options?: MutationOption<MetadataUpdateRequest>
 ): MutationRet<MetadataUpdateRequest> {
   const client = useAxiosClient();
@@ -55,7 +71,9 @@ export function useEditPhotoMutation(
     if (!name) {
       return;
     }
-    await client.put(`/api/photo/${name}`, payload);
+    await client.put(`/api/photo/${name}`, payload, {
+      headers: eTag ? { "If-Match": eTag } : undefined,
+    });
   }, options);
 }
 
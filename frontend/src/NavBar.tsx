@@ -2,7 +2,7 @@ import { useState } from "react";
 import { Link, Outlet, useNavigate } from "react-router-dom";
 
 import { Menu } from "./Icons";
-import { useUserFromQuery } from "./QueryHooks";
+import { useAuthProviders, useUserFromQuery } from "./QueryHooks";
 
 export default function NavBar(): React.ReactElement {
   const navigate = useNavigate();
@@ -12,6 +12,7 @@ export default function NavBar(): React.ReactElement {
     isLoading: isUserLoading,
     remove: removeUser,
   } = useUserFromQuery();
+  const { data: providers } = useAuthProviders();
 
   const onLogOut = () => {
     document.cookie = "SESSION=; Max-Age=-99999999;";
@@ -69,13 +70,17 @@ export default function NavBar(): React.ReactElement {
     );
   } else {
     navItems = (
-      <li className="nav-item">
-        <a href="/auth/github">
-          <span className="px-2 py-2 flex items-center hover:opacity-75">
-            <span className="ml-2">Log in with GitHub</span>
-          </span>
-        </a>
-      </li>
+      <>
+        {(providers || []).map((provider) => (
+          <li className="nav-item" key={provider.id}>
+            <a href={`/auth/login/${provider.id}`}>
+              <span className="px-2 py-2 flex items-center hover:opacity-75">
+                <span className="ml-2">Log in with {provider.displayName}</span>
+              </span>
+            </a>
+          </li>
+        ))}
+      </>
     );
   }
 
@@ -13,10 +13,17 @@ function Photo() {
       onSuccess: () => {
         navigate(-1);
       },
+      onError: (error) => {
+        if (error.response?.status === 409) {
+          window.alert(
+            `A photo named ${name} is already in the trash. Purge it first.`
+          );
+        }
+      },
     });
 
   const onDeleteClick = () => {
-    if (window.confirm(`Delete photo ${name}\nAre you sure?`)) {
+    if (window.confirm(`Move photo ${name} to the trash?`)) {
       deletePhoto();
     }
   };
@@ -8,7 +8,7 @@ function PhotoCard({ metadata }: { metadata: MetadataWithName }) {
     <Link to={`/photo/${metadata.name}`}>
       <div className="max-w-sm rounded shadow-lg overflow-hidden max-h-[300px] flex items-center">
         <LazyLoadImage
-          src={`/asset/photo/${metadata.name}`}
+          src={`/asset/photo/${metadata.name}?size=512`}
           alt={metadata.description}
           className="w-full"
         />
@@ -1,4 +1,9 @@
-import { AxiosError, AxiosInstance, isAxiosError } from "axios";
+import {
+  AxiosError,
+  AxiosInstance,
+  AxiosResponse,
+  isAxiosError,
+} from "axios";
 import { ReactElement, useEffect } from "react";
 import { useInView } from "react-intersection-observer";
 import {
@@ -9,18 +14,24 @@ import {
 } from "react-query";
 
 import { useAxiosClient } from "./AxiosContext";
-import { Metadata, MetadataWithName, TagsWithSample, User } from "./HttpTypes";
-
-async function get<T>(
+import {
+  AuthProvider,
+  Metadata,
+  MetadataWithETag,
+  MetadataWithName,
+  TagsWithSample,
+  User,
+} from "./HttpTypes";
+
+async function getResponse<T>(
   client: AxiosInstance,
   url: string,
   params?: any
-): Promise<T | undefined> {
+): Promise<AxiosResponse<T> | undefined> {
   try {
-    const resp = await client.get<T>(url, {
+    return await client.get<T>(url, {
       params,
     });
-    return resp.data;
   } catch (error) {
     if (isAxiosError(error)) {
       if (error.response?.status === 404) {
@@ -31,6 +42,15 @@ async function get<T>(
   }
 }
 
+async function get<T>(
+  client: AxiosInstance,
+  url: string,
+  params?: any
+): Promise<T | undefined> {
+  const resp = await getResponse<T>(client, url, params);
+  return resp?.data;
+}
+
 function makeObservationComponent<T>(
   data: InfiniteData<{ result: T; isLast: boolean }> | undefined,
   fetchNextPage: Function
@@ -72,6 +92,17 @@ export function useUserFromQuery(): UseQueryResult<User, AxiosError> {
   );
 }
 
+export function useAuthProviders(): UseQueryResult<
+  AuthProvider[] | undefined,
+  AxiosError
+> {
+  const client = useAxiosClient();
+  return useQuery(["auth-providers"], async () => {
+    const resp = await get<AuthProvider[]>(client, "/auth/providers");
+    return resp;
+  });
+}
+
 export function useTagsWithSampleInfinite(): UseInfiniteQueryWithScrollRet<TagsWithSample> {
   const client = useAxiosClient();
 
@@ -104,15 +135,21 @@ export function useTagsWithSampleInfinite(): UseInfiniteQueryWithScrollRet<TagsW
 
 export function useMetadata(
   name: string | undefined
-): UseQueryResult<Metadata | undefined, AxiosError> {
+): UseQueryResult<MetadataWithETag | undefined, AxiosError> {
   const client = useAxiosClient();
 
   return useQuery(["metadata", name], async () => {
     if (!name) {
       return undefined;
     }
-    const resp = await get<Metadata>(client, `/asset/metadata/${name}`);
-    return resp;
+    const resp = await getResponse<Metadata>(
+      client,
+      `/asset/metadata/${name}`
+    );
+    if (!resp) {
+      return undefined;
+    }
+    return { ...resp.data, eTag: resp.headers["etag"] };
   });
 }
 
@@ -181,3 +218,38 @@ export function useMetadatasByTagInfinite(
     ObservationComponent,
   };
 }
+
+export function useSearchInfinite(
+  query: string | undefined
+): UseInfiniteQueryWithScrollRet<MetadataWithName[]> {
+  const client = useAxiosClient();
+
+  const { data, error, isFetching, fetchNextPage } = useInfiniteQuery(
+    ["search", query],
+    async ({ pageParam = 0 }) => {
+      if (query === undefined) {
+        return { result: [], nextPage: undefined, isLast: true };
+      }
+      const resp =
+        (await get<MetadataWithName[]>(client, "/api/search", {
+          query,
+          page: pageParam,
+        })) || [];
+      const nextPage = resp.length > 0 ? pageParam + 1 : undefined;
+      return { result: resp, nextPage, isLast: !nextPage };
+    },
+    { getNextPageParam: (lastPage) => lastPage.nextPage, retry: false }
+  );
+
+  const ObservationComponent = makeObservationComponent(data, fetchNextPage);
+
+  return {
+    data: data?.pages.reduce(
+      (result, value) => result.concat(value.result),
+      new Array<MetadataWithName>()
+    ),
+    error,
+    isFetching,
+    ObservationComponent,
+  };
+}
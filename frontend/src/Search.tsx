@@ -1,23 +1,24 @@
+import { isAxiosError } from "axios";
 import { FormEvent, useState } from "react";
 
-import { MetadataWithName } from "./HttpTypes";
-import { useSearchMutation } from "./MutationHooks";
 import PhotoCard from "./PhotoCard";
+import { useSearchInfinite } from "./QueryHooks";
 import Spinner from "./Spinner";
 
 function Search() {
-  const [token, setToken] = useState("");
-  const [metadatas, setMetadatas] = useState<MetadataWithName[]>([]);
-  const { mutate: search, isLoading } = useSearchMutation({
-    onSuccess: (mds) => {
-      setMetadatas(mds);
-    },
-  });
+  const [input, setInput] = useState("");
+  const [query, setQuery] = useState<string | undefined>(undefined);
+  const {
+    data: metadatas,
+    error,
+    isFetching,
+    ObservationComponent,
+  } = useSearchInfinite(query);
 
   const onSubmit = (event: FormEvent<HTMLFormElement>) => {
     event.preventDefault();
 
-    search({ token });
+    setQuery(input);
   };
 
   return (
@@ -26,22 +27,32 @@ function Search() {
         <input
           className="mr-2 mb-1"
           type="text"
-          onChange={(event) => setToken(event.target.value)}
+          placeholder='tag:cat -tag:dog after:2022-01-01 "red car"'
+          onChange={(event) => setInput(event.target.value)}
         />
-        {isLoading ? (
-          <Spinner />
-        ) : (
-          <input
-            className="rounded-full px-5 py-2 bg-white inline-block"
-            type="submit"
-            value="Search"
-          />
+        <input
+          className="rounded-full px-5 py-2 bg-white inline-block"
+          type="submit"
+          value="Search"
+        />
+        <p className="text-sm text-gray-600">
+          Fields: tag:, by:, after:, before:, camera:. Prefix with - to
+          exclude, and combine with AND, OR and parentheses.
+        </p>
+        {isAxiosError(error) && error.response?.status === 400 && (
+          <p className="mt-2">{`${error.response.data}`}</p>
         )}
       </form>
       <div className="grid grid-cols-3 md:grid-cols-6 gap-4 items-center">
         {metadatas?.map((metadata) => (
           <PhotoCard key={metadata.name} metadata={metadata} />
         ))}
+        {isFetching && (
+          <div className="max-w-sm flex justify-center items-center">
+            <Spinner />
+          </div>
+        )}
+        <ObservationComponent />
       </div>
     </div>
   );
@@ -16,7 +16,7 @@ function Tags() {
     tagCards.push(
       <div
         key={tag}
-        style={{ backgroundImage: `url(/asset/photo/${metadata.name})` }}
+        style={{ backgroundImage: `url(/asset/photo/${metadata.name}?size=512)` }}
         className="max-w-sm rounded shadow-lg bg-cover bg-center bg-no-repeat"
       >
         <Link to={`/photos-by-tag/${tag}`}>